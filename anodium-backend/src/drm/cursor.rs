@@ -0,0 +1,124 @@
+use smithay::{
+    backend::allocator::Fourcc,
+    reexports::{
+        drm::{
+            control::{crtc, Device as ControlDevice},
+            Device as BasicDevice, DriverCapability,
+        },
+        gbm::{BufferObject, BufferObjectFlags, Device as GbmDevice},
+    },
+    utils::{Physical, Point},
+};
+use xcursor::parser::Image;
+
+use super::Device;
+
+/// Cursor image scanned out on the DRM cursor plane, so it does not have to be composited
+pub struct HardwareCursor {
+    size: (u32, u32),
+    bo: BufferObject<()>,
+    image: Option<Image>,
+}
+
+impl HardwareCursor {
+    pub fn new<D>(drm: &D, gbm: &GbmDevice<Device>) -> Option<Self>
+    where
+        D: BasicDevice,
+    {
+        let width = drm
+            .get_driver_capability(DriverCapability::CursorWidth)
+            .unwrap_or(64) as u32;
+        let height = drm
+            .get_driver_capability(DriverCapability::CursorHeight)
+            .unwrap_or(64) as u32;
+
+        let bo = gbm
+            .create_buffer_object::<()>(
+                width,
+                height,
+                Fourcc::Argb8888,
+                BufferObjectFlags::CURSOR | BufferObjectFlags::WRITE,
+            )
+            .map_err(|err| warn!("Hardware cursor unavailable: {}", err))
+            .ok()?;
+
+        Some(Self {
+            size: (width, height),
+            bo,
+            image: None,
+        })
+    }
+
+    /// Upload the image to the cursor plane
+    ///
+    /// Returns `false` if the image does not fit on the plane and has to be drawn in software.
+    pub fn set_image(&mut self, image: &Image) -> bool {
+        if self.image.as_ref() == Some(image) {
+            return true;
+        }
+
+        let (width, height) = self.size;
+        if image.width > width || image.height > height {
+            // Hides the plane, the software cursor takes over
+            self.image = None;
+            return false;
+        }
+
+        // The plane is bigger than the image, the rest of it stays transparent
+        let mut data = vec![0; (width * height * 4) as usize];
+        for y in 0..image.height {
+            for x in 0..image.width {
+                let src = ((y * image.width + x) * 4) as usize;
+                let dst = ((y * width + x) * 4) as usize;
+
+                if let [r, g, b, a] = image.pixels_rgba[src..src + 4] {
+                    // Argb8888 is stored as little-endian
+                    data[dst..dst + 4].copy_from_slice(&[b, g, r, a]);
+                }
+            }
+        }
+
+        if !matches!(self.bo.write(&data), Ok(Ok(()))) {
+            return false;
+        }
+
+        self.image = Some(image.clone());
+
+        true
+    }
+
+    /// The plane shows an image, the cursor does not have to be drawn in software
+    pub fn is_active(&self) -> bool {
+        self.image.is_some()
+    }
+
+    /// Show the cursor on the crtc with its hotspot at the given position, or hide it
+    ///
+    /// Returns `false` if the driver rejected the cursor.
+    #[allow(deprecated)]
+    pub fn update<D>(
+        &self,
+        drm: &D,
+        crtc: crtc::Handle,
+        position: Option<Point<i32, Physical>>,
+    ) -> bool
+    where
+        D: ControlDevice,
+    {
+        let (position, image) = match (position, &self.image) {
+            (Some(position), Some(image)) => (position, image),
+            _ => {
+                return drm
+                    .set_cursor2::<BufferObject<()>>(crtc, None, (0, 0))
+                    .is_ok()
+            }
+        };
+
+        let hotspot = (image.xhot as i32, image.yhot as i32);
+
+        drm.set_cursor2(crtc, Some(&self.bo), hotspot).is_ok()
+            && drm
+                .move_cursor(crtc, (position.x - hotspot.0, position.y - hotspot.1))
+                .is_ok()
+    }
+}
@@ -1,6 +1,6 @@
-use std::{cell::RefCell, path::Path, rc::Rc};
+use std::{cell::RefCell, path::Path, rc::Rc, time::Duration};
 
-use anyhow::Result;
+use anyhow::{bail, Result};
 use indexmap::IndexMap;
 use smithay::{
     backend::{
@@ -12,25 +12,101 @@ use smithay::{
             Bind, Frame, ImportMem, Renderer,
         },
         session::{auto::AutoSession, Signal as SessionSignal},
+        SwapBuffersError,
     },
     output::Mode as WlMode,
     reexports::{
-        calloop::LoopHandle,
+        calloop::{
+            timer::{TimeoutAction, Timer},
+            LoopHandle,
+        },
         drm::control::{connector, crtc, Device as _, ModeTypeFlags},
         gbm::Device as GbmDevice,
     },
     utils::{
         signaling::{Linkable, Signaler},
-        Rectangle,
+        Physical, Point, Rectangle,
     },
 };
 
-use super::{utils, Device, DrmDevice, DrmOutputId, DrmRenderer};
+use super::{cursor::HardwareCursor, utils, Device, DrmDevice, DrmOutputId, DrmRenderer};
 use crate::BackendHandler;
 
+/// Context losses in a row after which rendering is given up
+const MAX_CONTEXT_LOSSES: u32 = 3;
+
+/// Delay before rendering again after a failed frame, roughly one frame
+const RENDER_RETRY_DELAY: Duration = Duration::from_millis(16);
+
+/// Way to keep an output going after its frame failed to render
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RenderRecovery {
+    /// Render the frame again shortly
+    Retry,
+    /// Recreate the renderer, the old context is gone
+    RecreateRenderer,
+    /// The context keeps getting lost, close the compositor
+    GiveUp,
+}
+
+/// Decide how to recover from a failed render, after `context_losses` losses in a row
+fn render_recovery(err: &SwapBuffersError, context_losses: u32) -> RenderRecovery {
+    match err {
+        SwapBuffersError::ContextLost(_) if context_losses >= MAX_CONTEXT_LOSSES => {
+            RenderRecovery::GiveUp
+        }
+        SwapBuffersError::ContextLost(_) => RenderRecovery::RecreateRenderer,
+        SwapBuffersError::AlreadySwapped | SwapBuffersError::TemporaryFailure(_) => {
+            RenderRecovery::Retry
+        }
+    }
+}
+
+/// Side effects of recovering an output from a failed render
+///
+/// Split from the recovery itself, so the sequence can run without a gpu.
+trait RecoveryActions {
+    /// Lost contexts in a row so far
+    fn context_losses(&mut self) -> &mut u32;
+    /// Render the output again shortly
+    fn retry(&mut self);
+    /// Replace the renderer, along with the globals advertising it to clients
+    fn recreate_renderer(&mut self) -> Result<()>;
+    /// Close the compositor
+    fn give_up(&mut self);
+}
+
+fn recover<A: RecoveryActions>(actions: &mut A, err: SwapBuffersError) -> Result<()> {
+    match render_recovery(&err, *actions.context_losses()) {
+        RenderRecovery::Retry => {
+            warn!("Rendering failed, trying again on the next frame: {}", err);
+            actions.retry();
+            Ok(())
+        }
+        RenderRecovery::RecreateRenderer => {
+            *actions.context_losses() += 1;
+
+            warn!("Rendering context lost, recreating the renderer: {}", err);
+            actions.recreate_renderer()
+        }
+        RenderRecovery::GiveUp => {
+            actions.give_up();
+            bail!(
+                "Rendering context lost {} times in a row: {}",
+                MAX_CONTEXT_LOSSES + 1,
+                err
+            );
+        }
+    }
+}
+
 pub struct Gpu {
     drm: DrmDevice,
     drm_node: DrmNode,
+    /// `None` once the driver rejected the cursor plane, the cursor is drawn in software then
+    hardware_cursor: Option<HardwareCursor>,
+    /// Render the output again without waiting for a vblank, which only follows a queued frame
+    schedule_render: Box<dyn Fn(crtc::Handle)>,
     pub outputs: IndexMap<crtc::Handle, GpuConnector>,
 }
 
@@ -41,6 +117,7 @@ impl Gpu {
         session_signal: Signaler<SessionSignal>,
         path: &Path,
         drm_node: DrmNode,
+        mut use_non_desktop: impl FnMut(&str) -> bool,
     ) -> Result<Gpu>
     where
         D: BackendHandler,
@@ -48,6 +125,21 @@ impl Gpu {
     {
         let device = Device::open(session, path)?;
 
+        let retry_loop = event_loop.clone();
+        let schedule_render = Box::new(move |crtc: crtc::Handle| {
+            let timer = Timer::from_duration(RENDER_RETRY_DELAY);
+            let inserted = retry_loop.insert_source(timer, move |_, _, handler: &mut D| {
+                if let Err(err) = Gpu::drm_vblank(drm_node, crtc, handler) {
+                    error!("Render retry error: {}", err);
+                }
+                TimeoutAction::Drop
+            });
+
+            if let Err(err) = inserted {
+                error!("Failed to schedule a render retry: {}", err.error);
+            }
+        });
+
         let mut drm = DrmDevice::new(
             &event_loop,
             device.clone(),
@@ -64,6 +156,7 @@ impl Gpu {
         drm.inner_mut().link(session_signal.clone());
 
         let gbm = GbmDevice::new(device)?;
+        let hardware_cursor = HardwareCursor::new(drm.inner(), &gbm);
         let gbm = Rc::new(RefCell::new(gbm));
 
         let res = drm.scan_connectors();
@@ -93,6 +186,12 @@ impl Gpu {
                 connector_info.interface_id(),
             );
 
+            // Headsets and the like are driven by their own runtime, they are no desktop output
+            if utils::is_non_desktop(drm, connector) && !use_non_desktop(&connector_name) {
+                info!("Skipping non-desktop connector {}", connector_name);
+                continue;
+            }
+
             info!(
                 "Trying to setup connector {:?}-{} with crtc {:?} ({})",
                 connector_info.interface(),
@@ -127,6 +226,7 @@ impl Gpu {
             outputs.insert(
                 crtc,
                 GpuConnector {
+                    name: connector_name,
                     connector,
                     gbm_surface,
                     drm_modes: drm_modes.to_vec(),
@@ -138,6 +238,8 @@ impl Gpu {
         Ok(Gpu {
             drm,
             drm_node,
+            hardware_cursor,
+            schedule_render,
             outputs,
         })
     }
@@ -165,7 +267,7 @@ impl Gpu {
 
         let mut renderer = gpu_manager.renderer::<Gles2Renderbuffer>(&primary_gpu, &drm_node)?;
 
-        let age = {
+        let (dmabuf, age) = {
             let state = handler.backend_state().drm();
 
             let gpu = &mut state.gpu(&drm_node).unwrap();
@@ -174,42 +276,78 @@ impl Gpu {
             output.gbm_surface.frame_submitted()?;
 
             let (dmabuf, age) = output.gbm_surface.next_buffer()?;
-            renderer.bind(dmabuf).unwrap();
+            renderer.bind(dmabuf.clone()).unwrap();
 
-            age
+            (dmabuf, age)
         };
 
-        let pointer_image = {
+        let (pointer_image, hardware_cursor) = {
             let backend_state = handler.backend_state().drm();
 
             let frame = backend_state.pointer_image.get_image(1);
 
-            backend_state
-                .pointer_images
-                .iter()
-                .find_map(|(image, texture)| if image == &frame { Some(texture) } else { None })
-                .cloned()
-                .unwrap_or_else(|| {
-                    let texture = renderer
-                        .as_mut()
-                        .import_memory(
-                            &frame.pixels_rgba,
-                            (frame.width as i32, frame.height as i32).into(),
-                            false,
-                        )
-                        .expect("Failed to import cursor bitmap");
-                    backend_state.pointer_images.push((frame, texture.clone()));
-                    texture
-                })
+            let hardware_cursor = backend_state
+                .gpu(&drm_node)
+                .unwrap()
+                .hardware_cursor
+                .as_mut()
+                .map(|cursor| cursor.set_image(&frame))
+                .unwrap_or(false);
+
+            let pointer_image = if hardware_cursor {
+                None
+            } else {
+                let texture = backend_state
+                    .pointer_images
+                    .iter()
+                    .find_map(|(image, texture)| if image == &frame { Some(texture) } else { None })
+                    .cloned()
+                    .unwrap_or_else(|| {
+                        let texture = renderer
+                            .as_mut()
+                            .import_memory(
+                                &frame.pixels_rgba,
+                                (frame.width as i32, frame.height as i32).into(),
+                                false,
+                            )
+                            .expect("Failed to import cursor bitmap");
+                        backend_state.pointer_images.push((frame, texture.clone()));
+                        texture
+                    });
+
+                Some(texture)
+            };
+
+            (pointer_image, hardware_cursor)
         };
 
         let output_id = DrmOutputId { drm_node, crtc }.output_id();
-        handler.output_render(
+        let rendered = handler.output_render(
             renderer.as_mut(),
             &output_id,
             age as usize,
-            Some(&pointer_image),
-        )?;
+            pointer_image.as_ref(),
+        );
+
+        if let Err(err) = rendered {
+            drop(renderer);
+            drop(gpu_manager);
+            return Gpu::recover_from_render_error(drm_node, crtc, handler, err);
+        }
+        handler.backend_state().drm().context_losses = 0;
+
+        // Pointer motion moves the plane right away, this picks up image changes
+        let position = if hardware_cursor {
+            handler.cursor_location(&output_id)
+        } else {
+            None
+        };
+        handler
+            .backend_state()
+            .drm()
+            .gpu(&drm_node)
+            .unwrap()
+            .update_hardware_cursor(crtc, position);
 
         handler.send_frames(&output_id);
 
@@ -224,9 +362,67 @@ impl Gpu {
             .gbm_surface
             .queue_buffer()?;
 
+        handler.frame_presented(&output_id, Some(&dmabuf));
+
         Ok(())
     }
 
+    /// Show the hardware cursor on the crtc at the given position, or hide it
+    ///
+    /// Rejected cursors fall back to software, starting with the next frame.
+    pub fn update_hardware_cursor(
+        &mut self,
+        crtc: crtc::Handle,
+        position: Option<Point<i32, Physical>>,
+    ) {
+        let updated = match &self.hardware_cursor {
+            Some(cursor) => cursor.update(self.drm.inner(), crtc, position),
+            None => return,
+        };
+
+        if !updated {
+            warn!("Hardware cursor rejected, falling back to software cursor");
+            self.hardware_cursor = None;
+        }
+    }
+
+    /// Move the hardware cursor on the crtc without waiting for the next frame
+    pub fn move_hardware_cursor(
+        &mut self,
+        crtc: crtc::Handle,
+        position: Option<Point<i32, Physical>>,
+    ) {
+        let is_active = self
+            .hardware_cursor
+            .as_ref()
+            .map_or(false, HardwareCursor::is_active);
+
+        if is_active {
+            self.update_hardware_cursor(crtc, position);
+        }
+    }
+
+    /// Keep the output going after a failed render
+    ///
+    /// A lost context gets the renderer recreated, surfaces are imported into it again on their
+    /// next render and a blank frame is queued meanwhile. Other failures just render again.
+    fn recover_from_render_error<D>(
+        drm_node: DrmNode,
+        crtc: crtc::Handle,
+        handler: &mut D,
+        err: SwapBuffersError,
+    ) -> Result<()>
+    where
+        D: BackendHandler,
+    {
+        let mut actions = DrmRecovery {
+            drm_node,
+            crtc,
+            handler,
+        };
+        recover(&mut actions, err)
+    }
+
     /// Udev changed event
     pub fn changed_event<D>(drm_node: DrmNode, handler: &mut D)
     where
@@ -258,6 +454,8 @@ impl Gpu {
 }
 
 pub struct GpuConnector {
+    /// Connector name, like `eDP-1`
+    pub name: String,
     connector: connector::Handle,
     gbm_surface: GbmBufferedSurface<Rc<RefCell<GbmDevice<Device>>>, Device>,
     drm_modes: Vec<smithay::reexports::drm::control::Mode>,
@@ -307,3 +505,167 @@ impl GpuConnector {
         Ok(())
     }
 }
+
+/// Recovery of an output of a drm device
+struct DrmRecovery<'a, D> {
+    drm_node: DrmNode,
+    crtc: crtc::Handle,
+    handler: &'a mut D,
+}
+
+impl<D: BackendHandler> RecoveryActions for DrmRecovery<'_, D> {
+    fn context_losses(&mut self) -> &mut u32 {
+        &mut self.handler.backend_state().drm().context_losses
+    }
+
+    fn retry(&mut self) {
+        if let Some(gpu) = self.handler.backend_state().drm().gpu(&self.drm_node) {
+            (gpu.schedule_render)(self.crtc);
+        }
+    }
+
+    fn recreate_renderer(&mut self) -> Result<()> {
+        let state = self.handler.backend_state().drm();
+        *state.gpu_manager.borrow_mut() = GpuManager::new(EglGlesBackend, None)?;
+        // Textures of the old renderer can't be used anymore
+        state.pointer_images.clear();
+
+        super::init_renderer_globals(self.handler)?;
+        self.handler.renderer_recreated();
+
+        let state = self.handler.backend_state().drm();
+        let mut gpu_manager = state.gpu_manager.borrow_mut();
+        let mut renderer = gpu_manager.renderer(&self.drm_node, &self.drm_node)?;
+
+        let output = state
+            .gpus
+            .get_mut(&self.drm_node)
+            .and_then(|gpu| gpu.outputs.get_mut(&self.crtc));
+
+        match output {
+            Some(output) => output.clear(&mut renderer),
+            None => Ok(()),
+        }
+    }
+
+    fn give_up(&mut self) {
+        self.handler.close_compositor();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Output whose renderer keeps losing its context
+    #[derive(Debug, Default)]
+    struct MockOutput {
+        context_losses: u32,
+        retries: u32,
+        recreated: u32,
+        /// Recreating the renderer fails, like when the gpu is gone
+        recreate_fails: bool,
+        closed: bool,
+    }
+
+    impl RecoveryActions for MockOutput {
+        fn context_losses(&mut self) -> &mut u32 {
+            &mut self.context_losses
+        }
+
+        fn retry(&mut self) {
+            self.retries += 1;
+        }
+
+        fn recreate_renderer(&mut self) -> Result<()> {
+            if self.recreate_fails {
+                bail!("no gpu");
+            }
+            self.recreated += 1;
+            Ok(())
+        }
+
+        fn give_up(&mut self) {
+            self.closed = true;
+        }
+    }
+
+    fn context_lost() -> SwapBuffersError {
+        SwapBuffersError::ContextLost("context reset".into())
+    }
+
+    #[test]
+    fn lost_contexts_recreate_the_renderer_until_giving_up() {
+        let mut output = MockOutput::default();
+
+        for _ in 0..MAX_CONTEXT_LOSSES {
+            assert!(recover(&mut output, context_lost()).is_ok());
+        }
+        assert_eq!(output.recreated, MAX_CONTEXT_LOSSES);
+        assert!(!output.closed);
+
+        assert!(recover(&mut output, context_lost()).is_err());
+        assert_eq!(output.recreated, MAX_CONTEXT_LOSSES);
+        assert!(output.closed);
+    }
+
+    #[test]
+    fn temporary_failure_retries_without_recreating() {
+        let mut output = MockOutput::default();
+
+        let err = SwapBuffersError::TemporaryFailure("device busy".into());
+        assert!(recover(&mut output, err).is_ok());
+
+        assert_eq!(output.retries, 1);
+        assert_eq!(output.recreated, 0);
+        assert_eq!(output.context_losses, 0);
+    }
+
+    #[test]
+    fn failed_renderer_recreation_is_reported() {
+        let mut output = MockOutput {
+            recreate_fails: true,
+            ..Default::default()
+        };
+
+        assert!(recover(&mut output, context_lost()).is_err());
+        assert_eq!(output.context_losses, 1);
+        assert!(!output.closed);
+    }
+
+    #[test]
+    fn temporary_failures_are_retried() {
+        let err = SwapBuffersError::TemporaryFailure("device busy".into());
+        assert_eq!(render_recovery(&err, 0), RenderRecovery::Retry);
+
+        // Losses before don't matter, only a lost context counts towards giving up
+        assert_eq!(
+            render_recovery(&err, MAX_CONTEXT_LOSSES),
+            RenderRecovery::Retry
+        );
+        assert_eq!(
+            render_recovery(&SwapBuffersError::AlreadySwapped, 0),
+            RenderRecovery::Retry
+        );
+    }
+
+    #[test]
+    fn lost_context_recreates_the_renderer() {
+        for losses in 0..MAX_CONTEXT_LOSSES {
+            let err = SwapBuffersError::ContextLost("context reset".into());
+            assert_eq!(
+                render_recovery(&err, losses),
+                RenderRecovery::RecreateRenderer
+            );
+        }
+    }
+
+    #[test]
+    fn repeated_context_losses_give_up() {
+        let err = SwapBuffersError::ContextLost("context reset".into());
+        assert_eq!(
+            render_recovery(&err, MAX_CONTEXT_LOSSES),
+            RenderRecovery::GiveUp
+        );
+    }
+}
@@ -19,10 +19,12 @@ use smithay::{
     },
     output::{Mode as WlMode, PhysicalProperties},
     reexports::{calloop::EventLoop, drm::control::crtc, wayland_server::DisplayHandle},
-    utils::signaling::SignalToken,
+    utils::{signaling::SignalToken, Physical, Point},
     wayland::dmabuf::{DmabufGlobal, ImportError},
 };
 
+mod cursor;
+
 mod device;
 use device::{Device, DrmDevice};
 
@@ -63,6 +65,11 @@ pub struct DrmBackendState {
     primary_gpu: DrmNode,
     pointer_image: crate::utils::cursor::Cursor,
     pointer_images: Vec<(xcursor::parser::Image, Gles2Texture)>,
+    /// Renderer recreations since the last successful frame
+    context_losses: u32,
+    display: DisplayHandle,
+    /// Dmabuf global advertising the formats of the current renderer
+    dmabuf_global: Option<DmabufGlobal>,
     _restart_token: SignalToken,
 }
 
@@ -79,6 +86,16 @@ impl DrmBackendState {
         }
     }
 
+    pub fn reload_cursor_theme(&mut self, name: Option<&str>, size: Option<u32>) {
+        self.pointer_image = crate::utils::cursor::Cursor::load_theme(name, size);
+        // Textures of the old theme are never going to be used again
+        self.pointer_images.clear();
+    }
+
+    pub fn set_cursor_shape(&mut self, name: Option<&str>) {
+        self.pointer_image.set_shape(name);
+    }
+
     pub fn dmabuf_imported(
         &mut self,
         _dh: &DisplayHandle,
@@ -93,6 +110,16 @@ impl DrmBackendState {
             .map_err(|_| ImportError::Failed)
     }
 
+    pub fn move_cursor(&mut self, output: &OutputId, position: Option<Point<i32, Physical>>) {
+        let id = OUTPUT_ID_MAP.with(|map| map.borrow().get(output).cloned());
+
+        if let Some(id) = id {
+            if let Some(gpu) = self.gpus.get_mut(&id.drm_node) {
+                gpu.move_hardware_cursor(id.crtc, position);
+            }
+        }
+    }
+
     pub fn update_mode(&mut self, output: &OutputId, mode: &smithay::output::Mode) {
         let id = OUTPUT_ID_MAP.with(|map| map.borrow().get(output).cloned());
 
@@ -109,6 +136,63 @@ impl DrmBackendState {
     }
 }
 
+/// Make the renderer of the primary gpu available to clients
+///
+/// Runs again once the renderer got recreated, the globals have to follow the new one.
+fn init_renderer_globals<D>(handler: &mut D) -> Result<()>
+where
+    D: BackendHandler,
+{
+    let state = handler.backend_state().drm();
+    let display = state.display.clone();
+    let primary_gpu = state.primary_gpu;
+    let old_dmabuf_global = state.dmabuf_global.take();
+
+    let gpu_manager = state.gpu_manager.clone();
+    let mut gpu_manager = gpu_manager.borrow_mut();
+    #[cfg_attr(not(feature = "use_system_lib"), allow(unused_mut))]
+    let mut renderer = gpu_manager.renderer::<Gles2Renderbuffer>(&primary_gpu, &primary_gpu)?;
+
+    // Bind egl wl_display, uses c wayland libs
+    // TODO: replace with implementation of wl_drm to keep the backwards compatibility, but with no c libs
+    // The EGL implementation advertises wl_drm with the render node of the primary gpu and its
+    // own format list, the dmabuf global below only gets the dmabuf formats of the renderer.
+    #[cfg(feature = "use_system_lib")]
+    {
+        use smithay::backend::renderer::ImportEgl;
+
+        info!(
+            "Trying to initialize EGL Hardware Acceleration via {:?}",
+            primary_gpu
+        );
+        if renderer.bind_wl_display(&display).is_ok() {
+            info!("EGL hardware-acceleration enabled");
+        } else {
+            warn!("Failed to bind EGL wl_display, wl_drm buffers will not be supported");
+        }
+    }
+
+    let dmabuf_formats = renderer.dmabuf_formats().cloned().collect::<Vec<_>>();
+
+    drop(renderer);
+    drop(gpu_manager);
+
+    // Init dmabuf_globabl for primary gpu, replacing the one of the previous renderer
+    if let Some(global) = old_dmabuf_global {
+        handler
+            .dmabuf_state()
+            .destroy_global::<D::WaylandState>(&display, global);
+    }
+
+    let global =
+        handler
+            .dmabuf_state()
+            .create_global::<D::WaylandState, _>(&display, dmabuf_formats, None);
+    handler.backend_state().drm().dmabuf_global = Some(global);
+
+    Ok(())
+}
+
 pub fn run_drm_backend<D>(
     event_loop: &mut EventLoop<'static, D>,
     display: &DisplayHandle,
@@ -151,9 +235,14 @@ where
         session_signal,
         &primary_gpu_path,
         primary_gpu_node,
+        |name| handler.use_non_desktop_output(name),
     )?;
 
-    let outputs: Vec<_> = gpu.outputs.iter().map(|(crtc, _)| *crtc).collect();
+    let outputs: Vec<_> = gpu
+        .outputs
+        .iter()
+        .map(|(crtc, output)| (*crtc, output.name.clone()))
+        .collect();
 
     let mut gpus = HashMap::new();
     gpus.insert(primary_gpu_node, gpu);
@@ -167,51 +256,18 @@ where
         primary_gpu: primary_gpu_node,
         pointer_image: crate::utils::cursor::Cursor::load(),
         pointer_images: Vec::new(),
+        context_losses: 0,
+        display: display.clone(),
+        dmabuf_global: None,
         _restart_token: restart_token,
     });
 
     // TODO: This should handle potential SwapBuffersError::TemporaryFailure errors and retry
     handler.backend_state().drm().clear_all();
 
-    // Bind egl wl_display, uses c wayland libs
-    // TODO: replace with implementation of wl_drm to keep the backwards compatibility, but with no c libs
-    #[cfg(feature = "use_system_lib")]
-    {
-        use smithay::backend::renderer::ImportEgl;
-
-        let state = handler.backend_state().drm();
-        let mut gpu_manager = state.gpu_manager.borrow_mut();
-
-        let mut renderer = gpu_manager
-            .renderer::<Gles2Renderbuffer>(&state.primary_gpu, &state.primary_gpu)
-            .unwrap();
-
-        info!(
-            "Trying to initialize EGL Hardware Acceleration via {:?}",
-            state.primary_gpu
-        );
-        if renderer.bind_wl_display(display).is_ok() {
-            info!("EGL hardware-acceleration enabled");
-        }
-    }
-
-    // Init dmabuf_globabl for primary gpu
-    let dmabuf_formats = {
-        let state = handler.backend_state().drm();
-        let mut gpu_manager = state.gpu_manager.borrow_mut();
-
-        let renderer = gpu_manager
-            .renderer::<Gles2Renderbuffer>(&state.primary_gpu, &state.primary_gpu)
-            .unwrap();
-
-        renderer.dmabuf_formats().cloned().collect::<Vec<_>>()
-    };
-
-    handler
-        .dmabuf_state()
-        .create_global::<D::WaylandState, _>(display, dmabuf_formats, None);
+    init_renderer_globals(handler)?;
 
-    for crtc in outputs {
+    for (crtc, name) in outputs {
         let id = DrmOutputId {
             drm_node: primary_gpu_node,
             crtc,
@@ -226,7 +282,7 @@ where
 
         handler.output_created(crate::NewOutputDescriptor {
             id: id.output_id(),
-            name: "".into(),
+            name,
             physical_properties: PhysicalProperties {
                 size: (1920, 1080).into(),
                 subpixel: smithay::output::Subpixel::Unknown,
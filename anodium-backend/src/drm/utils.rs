@@ -1,4 +1,4 @@
-use smithay::reexports::drm::control::connector;
+use smithay::reexports::drm::control::{connector, Device as ControlDevice};
 
 pub fn format_connector_name(interface: connector::Interface, interface_id: u32) -> String {
     let other_short_name;
@@ -19,3 +19,20 @@ pub fn format_connector_name(interface: connector::Interface, interface_id: u32)
 
     format!("{}-{}", interface_short_name, interface_id)
 }
+
+/// Is the connector marked as not meant for the desktop, like the display of a VR headset
+pub fn is_non_desktop(drm: &impl ControlDevice, connector: connector::Handle) -> bool {
+    let props = match drm.get_properties(connector) {
+        Ok(props) => props,
+        Err(_) => return false,
+    };
+
+    let (handles, values) = props.as_props_and_values();
+    handles.iter().zip(values).any(|(handle, value)| {
+        let is_non_desktop = drm
+            .get_property(*handle)
+            .map_or(false, |info| info.name().to_bytes() == b"non-desktop");
+
+        is_non_desktop && *value != 0
+    })
+}
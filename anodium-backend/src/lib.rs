@@ -28,7 +28,7 @@ use smithay::{
         wayland_protocols::wp::linux_dmabuf::zv1::server::zwp_linux_dmabuf_v1,
         wayland_server::{DisplayHandle, GlobalDispatch},
     },
-    utils::{Physical, Rectangle},
+    utils::{Physical, Point, Rectangle},
     wayland::{
         buffer::BufferHandler,
         dmabuf::{DmabufGlobal, DmabufGlobalData, DmabufHandler, DmabufState, ImportError},
@@ -85,6 +85,32 @@ impl BackendState {
         }
     }
 
+    /// Reload the cursor theme, unset values fall back to `XCURSOR_THEME` and `XCURSOR_SIZE`
+    pub fn reload_cursor_theme(&mut self, name: Option<&str>, size: Option<u32>) {
+        match self {
+            BackendState::Drm(state) => state.reload_cursor_theme(name, size),
+            BackendState::None => {}
+        }
+    }
+
+    /// Move the hardware cursor of the output right away, `None` hides it
+    ///
+    /// Does nothing while the cursor is drawn in software, it moves with the next frame then.
+    pub fn move_cursor(&mut self, output_id: &OutputId, position: Option<Point<i32, Physical>>) {
+        match self {
+            BackendState::Drm(state) => state.move_cursor(output_id, position),
+            BackendState::None => {}
+        }
+    }
+
+    /// Show the named cursor of the theme instead of the default one, `None` goes back to it
+    pub fn set_cursor_shape(&mut self, name: Option<&str>) {
+        match self {
+            BackendState::Drm(state) => state.set_cursor_shape(name),
+            BackendState::None => {}
+        }
+    }
+
     pub fn dmabuf_imported(
         &mut self,
         dh: &DisplayHandle,
@@ -102,6 +128,10 @@ pub trait OutputHandler {
     /// Output was created
     fn output_created(&mut self, output: NewOutputDescriptor);
 
+    /// Should the non-desktop connector with the given name, like a VR headset, be used as a
+    /// regular output anyway
+    fn use_non_desktop_output(&mut self, name: &str) -> bool;
+
     /// Output got resized
     fn output_mode_updated(&mut self, output_id: &OutputId, mode: smithay::output::Mode);
 
@@ -117,8 +147,23 @@ pub trait OutputHandler {
         pointer_image: Option<&Gles2Texture>,
     ) -> Result<Option<Vec<Rectangle<i32, Physical>>>, smithay::backend::SwapBuffersError>;
 
+    /// Rendering context got lost and the renderer was recreated
+    ///
+    /// Textures created with the old renderer can't be drawn anymore, caches of them have to go.
+    fn renderer_recreated(&mut self);
+
     /// Send frames to clients on given output
     fn send_frames(&mut self, output_id: &OutputId);
+
+    /// Frame was rendered into the given buffer and submitted for presentation
+    ///
+    /// `None` when the backend renders into a buffer that can't be shared with clients.
+    fn frame_presented(&mut self, output: &OutputId, buffer: Option<&Dmabuf>);
+
+    /// Location of the default cursor image on the output, used to place the hardware cursor
+    ///
+    /// `None` hides the hardware cursor, e.g. when a client draws its own cursor surface.
+    fn cursor_location(&mut self, output: &OutputId) -> Option<Point<i32, Physical>>;
 }
 
 pub trait InputHandler {
@@ -16,23 +16,37 @@ use xcursor::{
 static FALLBACK_CURSOR_DATA: &[u8] = include_bytes!("../../../resources/cursor.rgba");
 
 pub struct Cursor {
+    theme: CursorTheme,
+    /// Images of the default cursor
     icons: Vec<Image>,
+    /// Name and images of the cursor shown instead of the default one
+    shape: Option<(String, Vec<Image>)>,
     size: u32,
     start_time: std::time::Instant,
 }
 
 impl Cursor {
+    /// Load the theme from `XCURSOR_THEME` and `XCURSOR_SIZE`
     pub fn load() -> Cursor {
-        let name = std::env::var("XCURSOR_THEME")
-            .ok()
-            .unwrap_or_else(|| "default".into());
-        let size = std::env::var("XCURSOR_SIZE")
-            .ok()
-            .and_then(|s| s.parse().ok())
-            .unwrap_or(24);
+        Self::load_theme(None, None)
+    }
+
+    /// Load the given theme, unset values fall back to `XCURSOR_THEME` and `XCURSOR_SIZE`
+    pub fn load_theme(name: Option<&str>, size: Option<u32>) -> Cursor {
+        let name = name.map(String::from).unwrap_or_else(|| {
+            std::env::var("XCURSOR_THEME")
+                .ok()
+                .unwrap_or_else(|| "default".into())
+        });
+        let size = size.unwrap_or_else(|| {
+            std::env::var("XCURSOR_SIZE")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(24)
+        });
 
         let theme = CursorTheme::load(&name);
-        let icons = load_icon(&theme)
+        let icons = load_icon(&theme, "default")
             .map_err(|err| warn!("Unable to load xcursor: {}, using fallback cursor", err))
             .unwrap_or_else(|_| {
                 vec![Image {
@@ -48,17 +62,36 @@ impl Cursor {
             });
 
         Cursor {
+            theme,
             icons,
+            shape: None,
             size,
             start_time: std::time::Instant::now(),
         }
     }
 
+    /// Show the named cursor of the theme instead of the default one, `None` goes back to it
+    ///
+    /// Shapes missing from the theme fall back to the default cursor.
+    pub fn set_shape(&mut self, name: Option<&str>) {
+        if self.shape.as_ref().map(|(current, _)| current.as_str()) == name {
+            return;
+        }
+
+        self.shape = name.and_then(|name| {
+            load_icon(&self.theme, name)
+                .map_err(|err| warn!("Unable to load xcursor: {}, using default cursor", err))
+                .ok()
+                .map(|icons| (name.to_owned(), icons))
+        });
+    }
+
     pub fn get_image(&self, scale: u32) -> Image {
         let size = self.size * scale;
         let millis = self.start_time.elapsed().as_millis();
 
-        frame(millis, size, &self.icons)
+        let icons = self.shape.as_ref().map_or(&self.icons, |(_, icons)| icons);
+        frame(millis, size, icons)
     }
 }
 
@@ -90,16 +123,18 @@ fn frame(mut millis: u128, size: u32, images: &[Image]) -> Image {
 
 #[derive(thiserror::Error, Debug)]
 enum Error {
-    #[error("Theme has no default cursor")]
-    NoDefaultCursor,
+    #[error("Theme has no {0} cursor")]
+    NoCursor(String),
     #[error("Error opening xcursor file: {0}")]
     File(#[from] std::io::Error),
     #[error("Failed to parse XCursor file")]
     Parse,
 }
 
-fn load_icon(theme: &CursorTheme) -> Result<Vec<Image>, Error> {
-    let icon_path = theme.load_icon("default").ok_or(Error::NoDefaultCursor)?;
+fn load_icon(theme: &CursorTheme, name: &str) -> Result<Vec<Image>, Error> {
+    let icon_path = theme
+        .load_icon(name)
+        .ok_or_else(|| Error::NoCursor(name.to_owned()))?;
     let mut cursor_file = std::fs::File::open(&icon_path)?;
     let mut cursor_data = Vec::new();
     cursor_file.read_to_end(&mut cursor_data)?;
@@ -1,7 +1,10 @@
 use std::{cell::RefCell, rc::Rc, time::Duration};
 
 use smithay::{
-    backend::winit::{self, WinitEvent},
+    backend::{
+        winit::{self, WinitEvent},
+        SwapBuffersError,
+    },
     output::{Mode, PhysicalProperties},
     reexports::{
         calloop::{
@@ -19,15 +22,33 @@ pub const OUTPUT_NAME: &str = "winit";
 
 pub fn run_winit<D>(
     event_loop: &mut EventLoop<'static, D>,
-    _display: &DisplayHandle,
+    display: &DisplayHandle,
     handler: &mut D,
 ) -> Result<(), ()>
 where
     D: BackendHandler + 'static,
 {
-    let (backend, mut input) = winit::init(None).map_err(|err| {
+    #[cfg_attr(not(feature = "use_system_lib"), allow(unused_mut))]
+    let (mut backend, mut input) = winit::init(None).map_err(|err| {
         error!("Failed to initialize Winit backend: {}", err);
     })?;
+
+    // Bind egl wl_display, so that clients using legacy wl_drm buffers can be imported.
+    // The EGL implementation advertises wl_drm with its device and formats, this backend has
+    // no dmabuf global, so the formats are not listed twice.
+    #[cfg(feature = "use_system_lib")]
+    {
+        use smithay::backend::renderer::ImportEgl;
+
+        if backend.renderer().bind_wl_display(display).is_ok() {
+            info!("EGL hardware-acceleration enabled");
+        } else {
+            warn!("Failed to bind EGL wl_display, wl_drm buffers will not be supported");
+        }
+    }
+    #[cfg(not(feature = "use_system_lib"))]
+    let _ = display;
+
     let backend = Rc::new(RefCell::new(backend));
 
     let size = backend.borrow().window_size().physical_size;
@@ -90,10 +111,22 @@ where
 
                     if backend.bind().is_ok() {
                         let age = backend.buffer_age().unwrap_or(0);
-                        let damage = handler
-                            .output_render(backend.renderer(), &output_id, age, None)
-                            .unwrap();
-                        backend.submit(damage.as_deref()).unwrap();
+
+                        match handler.output_render(backend.renderer(), &output_id, age, None) {
+                            Ok(damage) => {
+                                backend.submit(damage.as_deref()).unwrap();
+                                handler.frame_presented(&output_id, None);
+                            }
+                            // The renderer belongs to the host window, it can't be recreated
+                            Err(SwapBuffersError::ContextLost(err)) => {
+                                error!("Rendering context lost: {}", err);
+                                handler.close_compositor();
+
+                                return TimeoutAction::Drop;
+                            }
+                            // Try again on the next tick
+                            Err(err) => warn!("Rendering failed: {}", err),
+                        }
                     }
 
                     handler.send_frames(&output_id);
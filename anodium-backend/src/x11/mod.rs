@@ -3,6 +3,7 @@ use std::{
     os::unix::io::RawFd,
     rc::Rc,
     sync::{Arc, Mutex},
+    time::Duration,
 };
 
 use smithay::{
@@ -11,10 +12,15 @@ use smithay::{
         input::InputEvent,
         renderer::{gles2::Gles2Renderer, Bind, Unbind},
         x11::{WindowBuilder, X11Backend, X11Event, X11Handle, X11Surface},
+        SwapBuffersError,
     },
     output::{Mode, PhysicalProperties},
     reexports::{
-        calloop::{ping, EventLoop},
+        calloop::{
+            ping,
+            timer::{TimeoutAction, Timer},
+            EventLoop,
+        },
         gbm,
         wayland_server::DisplayHandle,
     },
@@ -25,6 +31,9 @@ use crate::{NewOutputDescriptor, OutputId};
 
 pub const OUTPUT_NAME: &str = "x11";
 
+/// Delay before rendering again after a failed frame, roughly one frame
+const RENDER_RETRY_DELAY: Duration = Duration::from_millis(16);
+
 struct OutputSurface {
     surface: X11Surface,
     window: smithay::backend::x11::Window,
@@ -145,8 +154,24 @@ where
         OutputSurfaceBuilder::new(&handle, device, &context, 1),
     ];
 
-    let renderer =
+    #[cfg_attr(not(feature = "use_system_lib"), allow(unused_mut))]
+    let mut renderer =
         unsafe { Gles2Renderer::new(context, None) }.expect("Failed to initialize renderer");
+
+    // Bind egl wl_display, so that clients using legacy wl_drm buffers can be imported.
+    // wl_drm is the only hardware buffer path of the nested backend, it comes with the device
+    // and formats of the EGL display, no dmabuf global lists them a second time.
+    #[cfg(feature = "use_system_lib")]
+    {
+        use smithay::backend::renderer::ImportEgl;
+
+        if renderer.bind_wl_display(display).is_ok() {
+            info!("EGL hardware-acceleration enabled");
+        } else {
+            warn!("Failed to bind EGL wl_display, wl_drm buffers will not be supported");
+        }
+    }
+
     let renderer = Rc::new(RefCell::new(renderer));
 
     new_x11_window(display, event_loop, handler, backend, renderer, x11_outputs)
@@ -186,6 +211,8 @@ where
         .handle()
         .insert_source(source, {
             let surface_datas = surface_datas.clone();
+            let retry = render.clone();
+            let retry_loop = event_loop.handle();
             move |_: (), _timer_handle, handler| {
                 let mut renderer = renderer.borrow_mut();
                 let surface_datas = &mut *surface_datas.borrow_mut();
@@ -201,40 +228,52 @@ where
                         .surface
                         .buffer()
                         .expect("gbm device was destroyed");
-                    if let Err(err) = renderer.bind(buffer) {
+                    if let Err(err) = renderer.bind(buffer.clone()) {
                         error!("Error while binding buffer: {}", err);
                     }
 
-                    let res: Result<(), ()> = {
-                        handler
-                            .output_render(
-                                &mut renderer,
-                                &surface_data.output_id,
-                                age as usize,
-                                None,
-                            )
-                            .ok();
-                        Ok(())
-                    };
+                    let res = handler.output_render(
+                        &mut renderer,
+                        &surface_data.output_id,
+                        age as usize,
+                        None,
+                    );
+
+                    // Unbind the buffer
+                    if let Err(err) = renderer.unbind() {
+                        error!("Error while unbinding buffer: {}", err);
+                    }
 
                     match res {
                         Ok(_) => {
-                            // Unbind the buffer
-                            if let Err(err) = renderer.unbind() {
-                                error!("Error while unbinding buffer: {}", err);
-                            }
-
                             // Submit the buffer
                             if let Err(err) = surface_data.surface.submit() {
                                 error!("Error submitting buffer for display: {}", err);
+                            } else {
+                                handler.frame_presented(&surface_data.output_id, Some(&buffer));
                             }
                         }
-                        Err(_) => {
-                            todo!();
-                            // if let SwapBuffersError::ContextLost(err) = err.into() {
-                            //     error!("Critical Rendering Error: {}", err);
-                            //     cb(BackendEvent::CloseCompositor {}, ddata.reborrow());
-                            // }
+                        // The renderer belongs to the host connection, it can't be recreated
+                        Err(SwapBuffersError::ContextLost(err)) => {
+                            error!("Rendering context lost: {}", err);
+                            handler.close_compositor();
+                            return;
+                        }
+                        Err(err) => {
+                            warn!("Rendering failed, trying again: {}", err);
+                            surface_data.rerender = true;
+
+                            // Pinging right away would spin as long as the failure lasts
+                            let retry = retry.clone();
+                            let timer = Timer::from_duration(RENDER_RETRY_DELAY);
+                            let inserted = retry_loop.insert_source(timer, move |_, _, _| {
+                                retry.ping();
+                                TimeoutAction::Drop
+                            });
+
+                            if let Err(err) = inserted {
+                                error!("Failed to schedule a render retry: {}", err.error);
+                            }
                         }
                     }
 
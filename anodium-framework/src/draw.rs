@@ -12,15 +12,10 @@ pub fn draw_cursor(
 ) -> SurfaceTree {
     let mut position = location.into();
     let ret = compositor::with_states(&surface, |states| {
-        Some(
-            states
-                .data_map
-                .get::<CursorImageSurfaceData>()
-                .unwrap()
-                .lock()
-                .unwrap()
-                .hotspot,
-        )
+        states
+            .data_map
+            .get::<CursorImageSurfaceData>()
+            .map(|data| data.lock().unwrap().hotspot)
     });
 
     position -= match ret {
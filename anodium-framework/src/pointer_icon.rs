@@ -39,6 +39,14 @@ impl PointerIcon {
         *self.pointer_icon.lock().unwrap() = status;
     }
 
+    /// Is the compositor supposed to draw its default cursor image
+    pub fn is_default_cursor(&self) -> bool {
+        matches!(
+            *self.pointer_icon.lock().unwrap(),
+            CursorImageStatus::Default
+        )
+    }
+
     pub fn prepare_dnd_icon(&self, location: Point<i32, Logical>) -> Option<SurfaceTree> {
         if let Some(surface) = &*self.dnd_surface.lock().unwrap() {
             surface
@@ -0,0 +1,467 @@
+use std::time::Duration;
+
+use slog_scope::{info, warn};
+use smithay::{
+    desktop::{Kind, Window},
+    output::Output,
+    reexports::calloop::timer::{TimeoutAction, Timer},
+    utils::Point,
+};
+use xkbcommon::xkb;
+
+use crate::{
+    config::{Key, Keybind, Length, NewWindowOutput, Overscan, SnapRegion, WindowGeometry},
+    data::{
+        output::OutputState,
+        seat::SeatState,
+        window::{WindowMode, WindowState},
+    },
+    layout, positioning, State,
+};
+
+/// Action that can be bound to an input
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action {
+    /// Spawn a process
+    Spawn(String),
+    /// Arrange windows of the active output into a cascade
+    CascadeWindows,
+    /// Arrange windows of the active output into a grid, without leaving floating mode
+    TileWindowsGrid,
+    /// Move the focused window by the given amount
+    MoveWindow { dx: Length, dy: Length },
+    /// Resize the focused window by the given amount
+    ResizeWindow { dx: Length, dy: Length },
+    /// Center the focused window on its output
+    CenterWindow,
+    /// Move and resize the focused window within the usable area of its output
+    SetWindowGeometry(WindowGeometry),
+    /// Snap the focused window to a region of its output, repeated snaps cycle the size
+    SnapWindow(SnapRegion),
+    /// Give a maximized, fullscreen or snapped focused window its floating geometry back
+    RestoreWindow,
+    /// Move the focused window to the output with the given name
+    ///
+    /// With `follow` the pointer is moved to the window as well.
+    MoveWindowToOutput { output: String, follow: bool },
+    /// Set the overscan compensation of the focused output
+    SetOverscan(Overscan),
+    /// Grow the overscan compensation of every edge of the focused output by the given pixels
+    ChangeOverscan { delta: i32 },
+    /// Set the brightness of the active output, in percent
+    SetBrightness { percent: u32 },
+    /// Change the brightness of the active output by the given amount of percent
+    ChangeBrightness { delta: i32 },
+    /// Save the geometry of all windows under the given name
+    SaveLayout(String),
+    /// Move windows back to the geometry saved under the given name
+    RestoreLayout(String),
+    /// Give compositor shortcuts back, while the focused client inhibits them
+    ///
+    /// This is the only action that still runs while shortcuts are inhibited.
+    ReleaseShortcutsInhibitor,
+    /// Cancel all grabs and shortcut inhibitors, to escape a misbehaving client
+    BreakGrabs,
+    /// Wait for the next key and run the matching keybind of the sequence
+    ///
+    /// The next key is not forwarded to the client, sequences can be nested.
+    KeySequence(Vec<Keybind>),
+    /// Focus the next window, from the most to the least recently focused
+    ///
+    /// Repeated cycling walks through all windows, until all modifiers but Shift are released.
+    FocusNextWindow,
+    /// Focus the previous window, from the most to the least recently focused
+    FocusPreviousWindow,
+    /// Ask the focused window to close
+    ///
+    /// Windows of the apps listed in `confirm_close` only close when the action runs again
+    /// within the close confirm timeout.
+    CloseWindow,
+    /// Exit the compositor, after asking all windows to close
+    ///
+    /// With `confirm` the action has to run again within the quit confirm timeout,
+    /// so a mis-hit keybinding doesn't end the session.
+    Quit { confirm: bool },
+    /// Exit and start the compositor binary again, with the same command line
+    ///
+    /// Nothing survives the restart, clients lose their connection and windows are gone.
+    /// The new process builds its config again, a reload that keeps the clients needs the
+    /// config to be loaded from a file first.
+    RestartCompositor,
+    /// Load the cursor theme again, unset values fall back to `XCURSOR_THEME` and `XCURSOR_SIZE`
+    ///
+    /// Cursor surfaces set by clients are not affected.
+    ReloadCursorTheme {
+        name: Option<String>,
+        size: Option<u32>,
+    },
+}
+
+impl Action {
+    /// Should the action be repeated while its key is held down
+    pub fn is_repeatable(&self) -> bool {
+        matches!(
+            self,
+            Self::MoveWindow { .. }
+                | Self::ResizeWindow { .. }
+                | Self::ChangeOverscan { .. }
+                | Self::ChangeBrightness { .. }
+        )
+    }
+}
+
+impl State {
+    /// Output under the pointer
+    pub fn active_output(&self) -> Option<Output> {
+        let pointer_pos = SeatState::for_seat(&self.seat).pointer_pos();
+        self.space.output_under(pointer_pos).next().cloned()
+    }
+
+    /// Window that has the keyboard focus
+    pub fn focused_window(&self) -> Option<Window> {
+        let surface = self.seat.get_keyboard()?.current_focus()?;
+        self.window_index.get(&surface).cloned()
+    }
+
+    /// Output that holds the keyboard focus
+    ///
+    /// Without a focused window, the output under the pointer is considered focused.
+    pub fn focused_output(&self) -> Option<Output> {
+        self.focused_window()
+            .and_then(|window| self.space.outputs_for_window(&window).first().cloned())
+            .or_else(|| self.active_output())
+    }
+
+    /// Connected output with the given name
+    pub fn output_by_name(&self, name: &str) -> Option<Output> {
+        self.space
+            .outputs()
+            .find(|output| output.name() == name)
+            .cloned()
+    }
+
+    /// Configured primary output, falls back to the first output if it is not connected
+    pub fn primary_output(&self) -> Option<Output> {
+        let configured = self
+            .config
+            .primary_output
+            .as_ref()
+            .and_then(|name| self.output_by_name(name));
+
+        configured.or_else(|| self.space.outputs().next().cloned())
+    }
+
+    /// Output new windows get placed on
+    pub fn new_window_output(&self) -> Option<Output> {
+        match self.config.new_window_output {
+            NewWindowOutput::Primary => self.primary_output(),
+            NewWindowOutput::Focused => self.focused_output(),
+        }
+    }
+
+    /// Keep running the action for as long as its key is held down
+    pub fn start_key_repeat(&mut self, action: Action) {
+        self.stop_key_repeat();
+
+        let delay = Duration::from_millis(self.config.key_repeat_delay);
+        let interval = Duration::from_millis(1000 / self.config.key_repeat_rate.max(1));
+
+        let token = self
+            ._loop_handle
+            .insert_source(Timer::from_duration(delay), move |_, _, data| {
+                data.state.run_action(&action);
+                TimeoutAction::ToDuration(interval)
+            })
+            .ok();
+
+        self.key_repeat = token;
+    }
+
+    pub fn stop_key_repeat(&mut self) {
+        if let Some(token) = self.key_repeat.take() {
+            self._loop_handle.remove(token);
+        }
+    }
+
+    /// Wait for the next key of a sequence, until the sequence timeout runs out
+    pub fn start_key_sequence(&mut self, binds: Vec<Keybind>) {
+        self.cancel_key_sequence();
+
+        // There is no overlay to show the hint on yet
+        let hint: Vec<String> = binds
+            .iter()
+            .map(|bind| {
+                let key = match bind.key {
+                    Key::Sym(keysym) => xkb::keysym_get_name(keysym),
+                    Key::Code(keycode) => format!("<{}>", keycode),
+                };
+                format!("{:?}+{} => {:?}", bind.modifiers, key, bind.action)
+            })
+            .collect();
+        info!("Awaiting the next key: {}", hint.join(", "));
+
+        let token = self
+            ._loop_handle
+            .insert_source(
+                Timer::from_duration(self.config.key_sequence_timeout),
+                |_, _, data| {
+                    data.state.key_sequence = None;
+                    TimeoutAction::Drop
+                },
+            )
+            .ok();
+
+        self.key_sequence = token.map(|token| (binds, token));
+    }
+
+    /// Stop waiting for the next key of a sequence, returns the keybinds it was waiting for
+    pub fn cancel_key_sequence(&mut self) -> Option<Vec<Keybind>> {
+        let (binds, token) = self.key_sequence.take()?;
+        self._loop_handle.remove(token);
+        Some(binds)
+    }
+
+    /// Ask the window to close, or wait for the close to be confirmed if its app needs it
+    ///
+    /// Only xdg windows can be closed for now.
+    pub fn close_window(&mut self, window: &Window) {
+        let xdg = if let Kind::Xdg(xdg) = window.toplevel() {
+            xdg
+        } else {
+            return;
+        };
+
+        let needs_confirm = layout::window_identity(window).map_or(false, |(app_id, _)| {
+            self.config.confirm_close.contains(&app_id)
+        });
+
+        let confirmed = match self.close_confirm.take() {
+            Some((pending, token)) => {
+                self._loop_handle.remove(token);
+                pending == *window
+            }
+            None => false,
+        };
+
+        if needs_confirm && !confirmed {
+            let timeout = self.config.close_confirm_timeout;
+
+            // There is no overlay to show the prompt on yet
+            info!("Close again within {:?} to close the window", timeout);
+
+            let token = self
+                ._loop_handle
+                .insert_source(Timer::from_duration(timeout), |_, _, data| {
+                    data.state.close_confirm = None;
+                    TimeoutAction::Drop
+                })
+                .ok();
+
+            self.close_confirm = token.map(|token| (window.clone(), token));
+            return;
+        }
+
+        xdg.send_close();
+    }
+
+    /// Exit the compositor, or wait for the quit to be confirmed by running it again
+    pub fn quit(&mut self, confirm: bool) {
+        if confirm && self.quit_confirm.is_none() {
+            let timeout = self.config.quit_confirm_timeout;
+
+            // There is no overlay to show the prompt on yet
+            info!("Quit again within {:?} to exit the compositor", timeout);
+
+            let token = self
+                ._loop_handle
+                .insert_source(Timer::from_duration(timeout), |_, _, data| {
+                    data.state.quit_confirm = None;
+                    TimeoutAction::Drop
+                })
+                .ok();
+
+            self.quit_confirm = token;
+            return;
+        }
+
+        if let Some(token) = self.quit_confirm.take() {
+            self._loop_handle.remove(token);
+        }
+
+        self.exit();
+    }
+
+    /// Ask all windows to close and stop the event loop
+    pub fn exit(&mut self) {
+        info!("Exiting the compositor");
+
+        // X11 windows go away together with Xwayland
+        for window in self.space.windows() {
+            if let Kind::Xdg(xdg) = window.toplevel() {
+                xdg.send_close();
+            }
+        }
+
+        // Clients are flushed once more after this dispatch, DRM and the session are released
+        // when the event loop returns
+        self.loop_signal.stop();
+    }
+
+    /// Move the window to the output with the given name, optionally taking the pointer along
+    pub fn move_window_to_output(&mut self, window: &Window, name: &str, follow: bool) {
+        let output = match self.output_by_name(name) {
+            Some(output) => output,
+            None => {
+                warn!("Can't move window to output {}, it is not connected", name);
+                return;
+            }
+        };
+
+        if self.space.outputs_for_window(window).first() != Some(&output) {
+            positioning::move_window_to_output(&mut self.space, window, &output);
+        }
+
+        if follow {
+            if let Some(geo) = self.space.window_bbox(window) {
+                let center = geo.loc + Point::from((geo.size.w / 2, geo.size.h / 2));
+                self.warp_pointer(center.to_f64());
+            }
+        }
+    }
+
+    /// Change the overscan compensation of the output, and fit its windows to the new area
+    pub fn set_overscan(&mut self, output: &Output, overscan: Overscan) {
+        info!("Overscan of {}: {:?}", output.name(), overscan);
+
+        OutputState::for_output(output).set_overscan(Some(overscan));
+        positioning::refit_windows(&mut self.space, output);
+    }
+
+    /// Grow the overscan compensation of every edge of the output, in pixels
+    pub fn change_overscan(&mut self, output: &Output, delta: i32) {
+        let output_geo = match self.space.output_geometry(output) {
+            Some(output_geo) => output_geo,
+            None => return,
+        };
+
+        let current = OutputState::for_output(output)
+            .overscan()
+            .unwrap_or_else(|| Overscan::uniform(Length::Px(0)));
+        let [top, right, bottom, left] = current.resolve(output_geo);
+        let inset = |px: i32| Length::Px((px + delta).max(0));
+
+        let overscan = Overscan {
+            top: inset(top),
+            right: inset(right),
+            bottom: inset(bottom),
+            left: inset(left),
+        };
+        self.set_overscan(output, overscan);
+    }
+
+    pub fn run_action(&mut self, action: &Action) {
+        match action {
+            Action::Spawn(cmd) => {
+                std::process::Command::new(cmd).spawn().ok();
+            }
+            Action::CascadeWindows => {
+                if let Some(output) = self.active_output() {
+                    positioning::cascade_windows(
+                        &mut self.space,
+                        &output,
+                        self.config.cascade_step,
+                    );
+                }
+            }
+            Action::TileWindowsGrid => {
+                if let Some(output) = self.active_output() {
+                    #[cfg(feature = "xwayland")]
+                    positioning::tile_windows_grid(&mut self.space, &self.xwayland, &output);
+                    #[cfg(not(feature = "xwayland"))]
+                    positioning::tile_windows_grid(&mut self.space, &output);
+                }
+            }
+            Action::MoveWindow { dx, dy } => {
+                if let Some(window) = self.focused_window() {
+                    let delta = positioning::resolve_delta(&self.space, &window, *dx, *dy);
+                    positioning::move_window_by(&mut self.space, &window, delta);
+                }
+            }
+            Action::ResizeWindow { dx, dy } => {
+                if let Some(window) = self.focused_window() {
+                    let delta = positioning::resolve_delta(&self.space, &window, *dx, *dy);
+                    positioning::resize_window_by(&self.space, &window, (delta.x, delta.y).into());
+                }
+            }
+            Action::CenterWindow => {
+                if let Some(window) = self.focused_window() {
+                    positioning::center_window(&mut self.space, &window);
+                }
+            }
+            Action::SetWindowGeometry(geometry) => {
+                if let Some(window) = self.focused_window() {
+                    positioning::set_window_geometry(&mut self.space, &window, geometry);
+                }
+            }
+            Action::SnapWindow(region) => {
+                if let Some(window) = self.focused_window() {
+                    positioning::snap_window(&mut self.space, &window, *region);
+                }
+            }
+            Action::RestoreWindow => {
+                if let Some(window) = self.focused_window() {
+                    if WindowState::for_window(&window).mode() != WindowMode::Floating {
+                        positioning::restore_window(&mut self.space, &window);
+                    }
+                }
+            }
+            Action::MoveWindowToOutput { output, follow } => {
+                if let Some(window) = self.focused_window() {
+                    self.move_window_to_output(&window, output, *follow);
+                }
+            }
+            Action::SetOverscan(overscan) => {
+                if let Some(output) = self.focused_output() {
+                    self.set_overscan(&output, *overscan);
+                }
+            }
+            Action::ChangeOverscan { delta } => {
+                if let Some(output) = self.focused_output() {
+                    self.change_overscan(&output, *delta);
+                }
+            }
+            Action::SetBrightness { percent } => {
+                if let Some(output) = self.active_output() {
+                    self.set_brightness(&output, *percent);
+                }
+            }
+            Action::ChangeBrightness { delta } => {
+                if let Some(output) = self.active_output() {
+                    self.change_brightness(&output, *delta);
+                }
+            }
+            Action::SaveLayout(name) => self.save_layout(name),
+            Action::RestoreLayout(name) => self.restore_layout(name),
+            Action::ReleaseShortcutsInhibitor => {
+                self.keyboard_shortcuts_inhibit_state.deactivate();
+            }
+            Action::BreakGrabs => self.break_grabs(),
+            Action::KeySequence(binds) => self.start_key_sequence(binds.clone()),
+            Action::FocusNextWindow => self.cycle_focus(true),
+            Action::FocusPreviousWindow => self.cycle_focus(false),
+            Action::CloseWindow => {
+                if let Some(window) = self.focused_window() {
+                    self.close_window(&window);
+                }
+            }
+            Action::Quit { confirm } => self.quit(*confirm),
+            Action::RestartCompositor => {
+                self.restart = true;
+                self.exit();
+            }
+            Action::ReloadCursorTheme { name, size } => {
+                self.backend.reload_cursor_theme(name.as_deref(), *size);
+            }
+        }
+    }
+}
@@ -0,0 +1,391 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Receiver, Sender},
+    thread,
+    time::Duration,
+};
+
+use dbus::blocking::Connection;
+use slog_scope::{debug, warn};
+use smithay::{
+    output::Output,
+    reexports::calloop::timer::{TimeoutAction, Timer},
+};
+
+use crate::State;
+
+const BACKLIGHT_DIR: &str = "/sys/class/backlight";
+const IIO_DIR: &str = "/sys/bus/iio/devices";
+
+/// Connector types of panels that are driven by a backlight
+const INTERNAL_CONNECTORS: [&str; 3] = ["eDP", "LVDS", "DSI"];
+
+const FADE_STEPS: u32 = 10;
+const FADE_STEP_DURATION: Duration = Duration::from_millis(15);
+const AUTO_BRIGHTNESS_INTERVAL: Duration = Duration::from_secs(5);
+const LOGIND_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Set the brightness through the logind session, which may write sysfs on behalf of the user
+fn logind_set_brightness(bus: &Connection, name: &str, brightness: u32) -> Result<(), dbus::Error> {
+    bus.with_proxy(
+        "org.freedesktop.login1",
+        "/org/freedesktop/login1/session/auto",
+        LOGIND_TIMEOUT,
+    )
+    .method_call(
+        "org.freedesktop.login1.Session",
+        "SetBrightness",
+        ("backlight", name, brightness),
+    )
+}
+
+/// Brightness level to set on a backlight device
+#[derive(Debug)]
+struct BrightnessWrite {
+    path: PathBuf,
+    brightness: u32,
+}
+
+/// Spawn the thread writing brightness levels
+///
+/// logind is reached over blocking D-Bus calls, which must not hold up the event loop.
+fn spawn_writer() -> Sender<BrightnessWrite> {
+    let (sender, receiver) = mpsc::channel();
+
+    let res = thread::Builder::new()
+        .name("backlight".into())
+        .spawn(move || run_writer(receiver));
+
+    if let Err(err) = res {
+        warn!("Failed to spawn the backlight thread: {}", err);
+    }
+
+    sender
+}
+
+fn run_writer(receiver: Receiver<BrightnessWrite>) {
+    // `None` when logind is not running
+    let bus = Connection::new_system()
+        .map_err(|err| warn!("Failed to connect to the system bus: {}", err))
+        .ok();
+    // Whether logind sets the brightness, once known the other path is not tried anymore
+    let mut logind_works = None;
+
+    while let Ok(write) = receiver.recv() {
+        // Only the latest level of each device matters when writes pile up behind logind
+        let mut writes: Vec<BrightnessWrite> = Vec::new();
+        for write in std::iter::once(write).chain(receiver.try_iter()) {
+            writes.retain(|w| w.path != write.path);
+            writes.push(write);
+        }
+
+        for write in &writes {
+            write_brightness(bus.as_ref(), &mut logind_works, write);
+        }
+    }
+}
+
+/// Set the brightness through logind, writing sysfs needs root otherwise
+fn write_brightness(
+    bus: Option<&Connection>,
+    logind_works: &mut Option<bool>,
+    write: &BrightnessWrite,
+) {
+    let name = write
+        .path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    if let Some(bus) = bus.filter(|_| *logind_works != Some(false)) {
+        match logind_set_brightness(bus, &name, write.brightness) {
+            Ok(()) => {
+                *logind_works = Some(true);
+                return;
+            }
+            Err(err) => {
+                debug!("logind failed to set brightness of {}: {}", name, err);
+                logind_works.get_or_insert(false);
+            }
+        }
+    }
+
+    if let Err(err) = fs::write(write.path.join("brightness"), write.brightness.to_string()) {
+        warn!("Failed to set brightness of {}: {}", name, err);
+    }
+}
+
+/// Brightness percentage of a raw brightness level, rounded to the nearest percent
+fn raw_to_percent(raw: u32, max: u32) -> u32 {
+    (raw.min(max) * 100 + max / 2) / max
+}
+
+/// Raw brightness level of a percentage, rounded to the nearest level
+fn percent_to_raw(percent: u32, max: u32) -> u32 {
+    (percent.min(100) * max + 50) / 100
+}
+
+fn read_u32(path: &Path) -> Option<u32> {
+    fs::read_to_string(path).ok()?.trim().parse().ok()
+}
+
+/// Backlight device exposed in `/sys/class/backlight`
+#[derive(Debug, Clone)]
+pub struct Backlight {
+    path: PathBuf,
+    max: u32,
+    writer: Sender<BrightnessWrite>,
+}
+
+impl Backlight {
+    pub fn enumerate() -> Vec<Self> {
+        let entries = match fs::read_dir(BACKLIGHT_DIR) {
+            Ok(entries) => entries,
+            Err(_) => return Vec::new(),
+        };
+
+        let mut devices = entries
+            .flatten()
+            .filter_map(|entry| {
+                let path = entry.path();
+                let max = read_u32(&path.join("max_brightness")).filter(|max| *max > 0)?;
+                Some((path, max))
+            })
+            .peekable();
+
+        if devices.peek().is_none() {
+            return Vec::new();
+        }
+
+        let writer = spawn_writer();
+        let mut backlights: Vec<Self> = devices
+            .map(|(path, max)| Self {
+                path,
+                max,
+                writer: writer.clone(),
+            })
+            .collect();
+
+        // Firmware interfaces are the most likely to actually work
+        backlights.sort_by_key(|b| {
+            match fs::read_to_string(b.path.join("type"))
+                .as_deref()
+                .map(str::trim)
+            {
+                Ok("firmware") => 0,
+                Ok("platform") => 1,
+                _ => 2,
+            }
+        });
+
+        backlights
+    }
+
+    pub fn name(&self) -> String {
+        self.path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    }
+
+    /// Raw brightness level, between 0 and the device maximum
+    pub fn raw(&self) -> Option<u32> {
+        read_u32(&self.path.join("brightness"))
+    }
+
+    pub fn percent(&self) -> Option<u32> {
+        self.raw().map(|raw| raw_to_percent(raw, self.max))
+    }
+
+    /// Set the brightness on the backlight thread, [`Self::raw`] reports it once written
+    pub fn set_raw(&self, brightness: u32) {
+        let write = BrightnessWrite {
+            path: self.path.clone(),
+            brightness: brightness.min(self.max),
+        };
+
+        if self.writer.send(write).is_err() {
+            warn!(
+                "Failed to set brightness of {}: no backlight thread",
+                self.name()
+            );
+        }
+    }
+
+    pub fn set_percent(&self, percent: u32) {
+        self.set_raw(percent_to_raw(percent, self.max));
+    }
+
+    /// Percentage reached by changing the brightness by `delta` percent
+    ///
+    /// Devices with few levels move by at least one level, so the step is never lost to rounding.
+    fn step_percent(&self, raw: u32, delta: i32) -> u32 {
+        let percent = (raw_to_percent(raw, self.max) as i32 + delta).clamp(0, 100) as u32;
+
+        if delta == 0 || percent_to_raw(percent, self.max) != raw {
+            return percent;
+        }
+
+        let raw = if delta > 0 {
+            (raw + 1).min(self.max)
+        } else {
+            raw.saturating_sub(1)
+        };
+        raw_to_percent(raw, self.max)
+    }
+}
+
+/// Illuminance reported by the first ambient light sensor, in lux
+pub fn ambient_light() -> Option<f64> {
+    fs::read_dir(IIO_DIR).ok()?.flatten().find_map(|entry| {
+        let path = entry.path();
+
+        if let Ok(lux) = fs::read_to_string(path.join("in_illuminance_input")) {
+            return lux.trim().parse().ok();
+        }
+
+        let raw: f64 = fs::read_to_string(path.join("in_illuminance_raw"))
+            .ok()?
+            .trim()
+            .parse()
+            .ok()?;
+        let scale: f64 = fs::read_to_string(path.join("in_illuminance_scale"))
+            .ok()
+            .and_then(|s| s.trim().parse().ok())
+            .unwrap_or(1.0);
+
+        Some(raw * scale)
+    })
+}
+
+/// Map the ambient illuminance onto a brightness percentage
+///
+/// Perceived brightness is roughly logarithmic, 1000 lux and above is treated as daylight.
+fn lux_to_percent(lux: f64) -> u32 {
+    let percent = (lux.max(1.0).log10() / 3.0) * 100.0;
+    (percent as u32).clamp(5, 100)
+}
+
+impl State {
+    /// Backlight driving the given output, only internal panels have one
+    pub fn backlight_for_output(&self, output: &Output) -> Option<Backlight> {
+        let name = output.name();
+        if !INTERNAL_CONNECTORS.iter().any(|c| name.starts_with(c)) {
+            return None;
+        }
+        self.backlights.first().cloned()
+    }
+
+    /// Smoothly step the output brightness towards the given percentage
+    pub fn set_brightness(&mut self, output: &Output, percent: u32) {
+        let backlight = match self.backlight_for_output(output) {
+            Some(backlight) => backlight,
+            None => return,
+        };
+
+        if let Some(token) = self.brightness_fade.take() {
+            self._loop_handle.remove(token);
+        }
+
+        let target = percent.min(100);
+        let start = backlight.percent().unwrap_or(target);
+        if start == target {
+            return;
+        }
+        debug!("Brightness of {}: {}%", output.name(), target);
+
+        let mut step = 0;
+        self.brightness_fade = self
+            ._loop_handle
+            .insert_source(Timer::immediate(), move |_, _, data| {
+                step += 1;
+
+                let current =
+                    start as i64 + (target as i64 - start as i64) * step as i64 / FADE_STEPS as i64;
+                backlight.set_percent(current as u32);
+
+                if step < FADE_STEPS {
+                    TimeoutAction::ToDuration(FADE_STEP_DURATION)
+                } else {
+                    data.state.brightness_fade = None;
+                    TimeoutAction::Drop
+                }
+            })
+            .ok();
+    }
+
+    /// Change the output brightness by the given amount of percent
+    pub fn change_brightness(&mut self, output: &Output, delta: i32) {
+        let backlight = match self.backlight_for_output(output) {
+            Some(backlight) => backlight,
+            None => return,
+        };
+
+        if let Some(raw) = backlight.raw() {
+            self.set_brightness(output, backlight.step_percent(raw, delta));
+        }
+    }
+
+    /// Periodically adjust the brightness of internal panels to the ambient light
+    pub fn start_auto_brightness(&mut self) {
+        if self.backlights.is_empty() || ambient_light().is_none() {
+            return;
+        }
+
+        self._loop_handle
+            .insert_source(Timer::immediate(), |_, _, data| {
+                let state = &mut data.state;
+
+                if state.config.auto_brightness {
+                    if let Some(lux) = ambient_light() {
+                        let outputs: Vec<Output> = state.space.outputs().cloned().collect();
+                        for output in outputs {
+                            state.set_brightness(&output, lux_to_percent(lux));
+                        }
+                    }
+                }
+
+                TimeoutAction::ToDuration(AUTO_BRIGHTNESS_INTERVAL)
+            })
+            .ok();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn conversions_round_trip_on_coarse_devices() {
+        for max in [1, 7, 15, 100] {
+            for raw in 0..=max {
+                assert_eq!(percent_to_raw(raw_to_percent(raw, max), max), raw);
+            }
+        }
+    }
+
+    #[test]
+    fn conversions_round() {
+        assert_eq!(raw_to_percent(1, 7), 14);
+        assert_eq!(raw_to_percent(7, 7), 100);
+        assert_eq!(percent_to_raw(50, 7), 4);
+        assert_eq!(percent_to_raw(100, 15), 15);
+    }
+
+    #[test]
+    fn steps_move_coarse_devices_by_a_level() {
+        let backlight = Backlight {
+            path: PathBuf::new(),
+            max: 7,
+            writer: mpsc::channel().0,
+        };
+
+        // 5% of 7 levels rounds back to the same level
+        assert_eq!(percent_to_raw(backlight.step_percent(3, 5), 7), 4);
+        assert_eq!(percent_to_raw(backlight.step_percent(3, -5), 7), 2);
+
+        assert_eq!(backlight.step_percent(7, 5), 100);
+        assert_eq!(backlight.step_percent(0, -5), 0);
+    }
+}
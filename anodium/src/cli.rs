@@ -13,4 +13,7 @@ pub struct AnodiumCliOptions {
     /// Path of anodium config
     #[clap(short, long, default_value = "./config.rhai")]
     pub config: PathBuf,
+    /// Log how long commits, renders and layer arranges take
+    #[clap(long)]
+    pub log_timings: bool,
 }
@@ -0,0 +1,227 @@
+//! Close animation, destroyed windows fade out from a snapshot of their last frame
+//!
+//! The texture of a surface can't be reached anymore once the client destroyed it,
+//! so the snapshot is refreshed on every render while the window is alive.
+//! Only the toplevel surface is captured, subsurfaces and popups disappear right away.
+
+use std::{
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Instant,
+};
+
+use smithay::{
+    backend::renderer::{
+        gles2::{Gles2Error, Gles2Frame, Gles2Renderer, Gles2Texture},
+        utils::RendererSurfaceStateUserData,
+        Frame, Texture,
+    },
+    desktop::{
+        space::{RenderElement, RenderZindex, SpaceOutputTuple},
+        Space, Window,
+    },
+    output::Output,
+    utils::{Buffer, IsAlive, Logical, Physical, Point, Rectangle, Scale, Transform},
+    wayland::compositor::{self, SurfaceAttributes},
+};
+
+use crate::{data::window::WindowState, State};
+
+/// Last rendered frame of a window's toplevel surface
+#[derive(Debug, Clone)]
+pub struct WindowSnapshot {
+    texture: Gles2Texture,
+    /// Bounds of the surface, in global space coordinates
+    geometry: Rectangle<i32, Logical>,
+}
+
+impl WindowSnapshot {
+    /// Capture the texture the window's toplevel surface was last rendered with
+    fn capture(renderer: &Gles2Renderer, space: &Space, window: &Window) -> Option<Self> {
+        let loc = space.window_location(window)? - window.geometry().loc;
+
+        compositor::with_states(window.toplevel().wl_surface(), |states| {
+            let scale = states
+                .cached_state
+                .current::<SurfaceAttributes>()
+                .buffer_scale
+                .max(1);
+
+            let data = states.data_map.get::<RendererSurfaceStateUserData>()?;
+            let texture = data.borrow().texture(renderer)?.clone();
+            let size = texture.size().to_logical(scale, Transform::Normal);
+
+            Some(Self {
+                texture,
+                geometry: Rectangle::from_loc_and_size(loc, size),
+            })
+        })
+    }
+}
+
+/// Destroyed window that is still fading out
+#[derive(Debug)]
+pub struct ClosingWindow {
+    id: usize,
+    snapshot: WindowSnapshot,
+    started: Instant,
+}
+
+impl ClosingWindow {
+    fn new(snapshot: WindowSnapshot) -> Self {
+        // Ids are never reused, so every closing window keeps its own damage history
+        static NEXT_ID: AtomicUsize = AtomicUsize::new(0x1000);
+
+        Self {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            snapshot,
+            started: Instant::now(),
+        }
+    }
+}
+
+impl State {
+    /// Refresh the snapshots of the windows shown on the output
+    pub fn snapshot_windows(&self, renderer: &Gles2Renderer, output: &Output) {
+        if self.config.close_animation.is_none() {
+            return;
+        }
+
+        for window in self.space.windows() {
+            // Surfaces of a destroyed window can't be accessed anymore
+            if !window.toplevel().alive() || !self.space.outputs_for_window(window).contains(output)
+            {
+                continue;
+            }
+
+            if let Some(snapshot) = WindowSnapshot::capture(renderer, &self.space, window) {
+                WindowState::for_window(window).set_snapshot(Some(snapshot));
+            }
+        }
+    }
+
+    /// Keep the last frame of destroyed windows around, and drop the ones that finished fading out
+    ///
+    /// Has to run before [`Space::refresh`], which forgets the destroyed windows.
+    pub fn update_closing_windows(&mut self) {
+        let duration = match self.config.close_animation {
+            Some(duration) => duration,
+            None => {
+                self.closing_windows.clear();
+                return;
+            }
+        };
+
+        // Dropping the snapshot frees its texture, even while nothing gets rendered
+        self.closing_windows
+            .retain(|closing| closing.started.elapsed() < duration);
+
+        let closed = self
+            .space
+            .windows()
+            .filter(|window| !window.toplevel().alive())
+            .filter_map(|window| WindowState::for_window(window).take_snapshot());
+
+        self.closing_windows.extend(closed.map(ClosingWindow::new));
+    }
+
+    /// Elements of the windows fading out on the output
+    pub fn closing_elements(&self, output: &Output) -> Vec<ClosingWindowElement> {
+        let (duration, output_geo) = match (
+            self.config.close_animation,
+            self.space.output_geometry(output),
+        ) {
+            (Some(duration), Some(output_geo)) => (duration, output_geo),
+            _ => return Vec::new(),
+        };
+
+        self.closing_windows
+            .iter()
+            .filter(|closing| closing.snapshot.geometry.overlaps(output_geo))
+            .map(|closing| {
+                let progress = closing.started.elapsed().as_secs_f32()
+                    / duration.as_secs_f32().max(f32::EPSILON);
+
+                let mut geometry = closing.snapshot.geometry;
+                // Custom elements are positioned relative to the output
+                geometry.loc -= output_geo.loc;
+
+                ClosingWindowElement {
+                    id: closing.id,
+                    texture: closing.snapshot.texture.clone(),
+                    geometry,
+                    alpha: (1.0 - progress).clamp(0.0, 1.0),
+                }
+            })
+            .collect()
+    }
+}
+
+/// Snapshot of a destroyed window, drawn with decreasing opacity
+#[derive(Debug, Clone)]
+pub struct ClosingWindowElement {
+    id: usize,
+    texture: Gles2Texture,
+    geometry: Rectangle<i32, Logical>,
+    alpha: f32,
+}
+
+impl RenderElement<Gles2Renderer> for ClosingWindowElement {
+    fn id(&self) -> usize {
+        self.id
+    }
+
+    fn location(&self, scale: impl Into<Scale<f64>>) -> Point<f64, Physical> {
+        self.geometry.loc.to_f64().to_physical(scale)
+    }
+
+    fn geometry(&self, scale: impl Into<Scale<f64>>) -> Rectangle<i32, Physical> {
+        self.geometry.to_physical_precise_round(scale)
+    }
+
+    fn accumulated_damage(
+        &self,
+        scale: impl Into<Scale<f64>>,
+        _: Option<SpaceOutputTuple<'_, '_>>,
+    ) -> Vec<Rectangle<i32, Physical>> {
+        // The opacity changes on every frame
+        vec![Rectangle::from_loc_and_size(
+            (0, 0),
+            self.geometry(scale).size,
+        )]
+    }
+
+    fn draw(
+        &self,
+        _renderer: &mut Gles2Renderer,
+        frame: &mut Gles2Frame,
+        scale: impl Into<Scale<f64>>,
+        location: Point<f64, Physical>,
+        damage: &[Rectangle<i32, Physical>],
+        _log: &slog::Logger,
+    ) -> Result<(), Gles2Error> {
+        let src =
+            Rectangle::<f64, Buffer>::from_loc_and_size((0.0, 0.0), self.texture.size().to_f64());
+        let dst =
+            Rectangle::from_loc_and_size(location, self.geometry.size.to_f64().to_physical(scale));
+
+        frame.render_texture_from_to(
+            &self.texture,
+            src,
+            dst,
+            damage,
+            Transform::Normal,
+            self.alpha,
+        )
+    }
+
+    fn opaque_regions(
+        &self,
+        _scale: impl Into<Scale<f64>>,
+    ) -> Option<Vec<Rectangle<i32, Physical>>> {
+        None
+    }
+
+    fn z_index(&self) -> u8 {
+        RenderZindex::Shell as u8
+    }
+}
@@ -0,0 +1,601 @@
+use std::{collections::HashMap, path::PathBuf, time::Duration};
+
+use smithay::{
+    input::keyboard::{keysyms as xkb, KeysymHandle, ModifiersState},
+    utils::{Logical, Rectangle},
+};
+
+use crate::action::Action;
+
+bitflags::bitflags! {
+    pub struct Modifiers: u8 {
+        const CTRL  = 0b0001;
+        const ALT   = 0b0010;
+        const SHIFT = 0b0100;
+        const LOGO  = 0b1000;
+    }
+}
+
+impl From<&ModifiersState> for Modifiers {
+    fn from(state: &ModifiersState) -> Self {
+        let mut modifiers = Self::empty();
+        modifiers.set(Self::CTRL, state.ctrl);
+        modifiers.set(Self::ALT, state.alt);
+        modifiers.set(Self::SHIFT, state.shift);
+        modifiers.set(Self::LOGO, state.logo);
+        modifiers
+    }
+}
+
+/// Key that triggers a keybind
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Key {
+    /// Keysym produced by the current layout, `KEY_q` is the q of the layout
+    Sym(u32),
+    /// Physical key whatever the layout, as an evdev code from `linux/input-event-codes.h`
+    Code(u32),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Keybind {
+    pub modifiers: Modifiers,
+    pub key: Key,
+    pub action: Action,
+}
+
+impl Keybind {
+    pub fn new(modifiers: Modifiers, keysym: u32, action: Action) -> Self {
+        Self {
+            modifiers,
+            key: Key::Sym(keysym),
+            action,
+        }
+    }
+
+    /// Keybind on a physical key, it stays in place when the layout changes
+    pub fn keycode(modifiers: Modifiers, keycode: u32, action: Action) -> Self {
+        Self {
+            modifiers,
+            key: Key::Code(keycode),
+            action,
+        }
+    }
+
+    /// Is the keybind triggered by the pressed key
+    pub fn matches(&self, modifiers: Modifiers, handle: &KeysymHandle<'_>) -> bool {
+        self.matches_key(
+            modifiers,
+            handle.raw_code(),
+            handle.modified_sym(),
+            handle.raw_syms(),
+        )
+    }
+
+    /// Is the keybind triggered by the key with the given keycode and keysyms
+    ///
+    /// `modified_sym` is the keysym with modifiers applied, `raw_syms` the ones without.
+    fn matches_key(
+        &self,
+        modifiers: Modifiers,
+        raw_code: u32,
+        modified_sym: u32,
+        raw_syms: &[u32],
+    ) -> bool {
+        match self.key {
+            // xkb keycodes are offset by 8 from evdev ones
+            Key::Code(keycode) => self.modifiers == modifiers && raw_code == keycode + 8,
+            Key::Sym(keysym) => {
+                if modified_sym != keysym {
+                    return false;
+                }
+
+                if self.modifiers == modifiers {
+                    return true;
+                }
+
+                // Keysyms on the shifted level, like `exclam`, are bound without the Shift it
+                // takes to reach them. Other levels are reached through modifiers that
+                // keybinds don't track, like AltGr, so they match as they are.
+                let shifted_level = !raw_syms.contains(&keysym);
+                shifted_level && self.modifiers | Modifiers::SHIFT == modifiers
+            }
+        }
+    }
+}
+
+/// Length in pixels, or in percent of the usable area of the window's output
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Length {
+    Px(i32),
+    Percent(i32),
+}
+
+impl Length {
+    /// Length in pixels, percentages are taken of `total`
+    pub fn resolve(self, total: i32) -> i32 {
+        match self {
+            Self::Px(px) => px,
+            Self::Percent(percent) => total * percent / 100,
+        }
+    }
+}
+
+/// Part of the usable area a window can be snapped to
+///
+/// Snapping to the same region again cycles the size through a half, a third and two thirds,
+/// quarters cycle their width.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SnapRegion {
+    LeftHalf,
+    RightHalf,
+    TopHalf,
+    BottomHalf,
+    TopLeftQuarter,
+    TopRightQuarter,
+    BottomLeftQuarter,
+    BottomRightQuarter,
+    LeftThird,
+    CenterThird,
+    RightThird,
+    Maximized,
+}
+
+/// Trigger zones of edge snapping, in logical pixels
+///
+/// The top edge maximizes, the left and right edges snap to halves and their ends to quarters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EdgeSnap {
+    /// Distance from an edge of the output that triggers
+    pub edge: i32,
+    /// Length of the ends of the left and right edges that snap to quarters
+    pub corner: i32,
+}
+
+/// Inset of each edge of an output, for TVs that cut off the edges of the image
+///
+/// Percentages are taken of the output size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Overscan {
+    pub top: Length,
+    pub right: Length,
+    pub bottom: Length,
+    pub left: Length,
+}
+
+impl Overscan {
+    /// Same inset on every edge
+    pub fn uniform(inset: Length) -> Self {
+        Self {
+            top: inset,
+            right: inset,
+            bottom: inset,
+            left: inset,
+        }
+    }
+
+    /// Insets in pixels, as top, right, bottom and left
+    pub fn resolve(&self, rect: Rectangle<i32, Logical>) -> [i32; 4] {
+        [
+            self.top.resolve(rect.size.h).max(0),
+            self.right.resolve(rect.size.w).max(0),
+            self.bottom.resolve(rect.size.h).max(0),
+            self.left.resolve(rect.size.w).max(0),
+        ]
+    }
+
+    /// Shrink the rectangle by the insets, it never gets smaller than a pixel
+    pub fn apply(&self, rect: Rectangle<i32, Logical>) -> Rectangle<i32, Logical> {
+        let [top, right, bottom, left] = self.resolve(rect);
+
+        Rectangle::from_loc_and_size(
+            (rect.loc.x + left, rect.loc.y + top),
+            (
+                (rect.size.w - left - right).max(1),
+                (rect.size.h - top - bottom).max(1),
+            ),
+        )
+    }
+}
+
+/// Window geometry relative to the usable area of the window's output, unset values are kept
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct WindowGeometry {
+    pub x: Option<Length>,
+    pub y: Option<Length>,
+    pub w: Option<Length>,
+    pub h: Option<Length>,
+}
+
+/// Pointer input that triggers a pointer bind
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PointerInput {
+    /// Button, as an evdev code like `BTN_RIGHT` (`0x111`)
+    Button(u32),
+    /// Wheel clicks, continuous scrolling like on touchpads is always forwarded
+    ScrollUp,
+    ScrollDown,
+    ScrollLeft,
+    ScrollRight,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PointerBind {
+    pub modifiers: Modifiers,
+    pub input: PointerInput,
+    pub action: Action,
+}
+
+impl PointerBind {
+    pub fn new(modifiers: Modifiers, input: PointerInput, action: Action) -> Self {
+        Self {
+            modifiers,
+            input,
+            action,
+        }
+    }
+}
+
+/// How keyboard focus follows the pointer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FocusMode {
+    /// Focus changes only when a window gets clicked
+    ClickToFocus,
+    /// Focus follows the pointer, empty areas keep the last focused window
+    Sloppy,
+    /// Focus follows the pointer, empty areas remove the focus
+    Strict,
+}
+
+/// Which layer surfaces above the windows let the pointer through to the windows below
+///
+/// Only surfaces that take no keyboard input, like most bars and overlays, are affected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LayerPassthrough {
+    /// Every surface takes the pointer within its input region
+    Never,
+    /// Surfaces let the pointer through to fullscreen windows, like games
+    OverFullscreen,
+    /// Surfaces always let the pointer through
+    Always,
+}
+
+/// Where floating windows go when the usable area of their output changes size
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputResizePlacement {
+    /// Windows keep their coordinates
+    Absolute,
+    /// Windows keep their relative position, staying within the output
+    Proportional,
+}
+
+/// How the wallpaper image is fitted to the output
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WallpaperMode {
+    /// Scaled to cover the whole output, cropping what does not fit
+    Fill,
+    /// Scaled to fit inside the output, the rest is filled with the fill color
+    Fit,
+    /// Stretched to the output size, ignoring the aspect ratio
+    Stretch,
+    /// Unscaled in the middle of the output
+    Center,
+    /// Unscaled and repeated from the top-left corner
+    Tile,
+}
+
+#[derive(Debug, Clone)]
+pub struct WallpaperConfig {
+    /// Image file, or directory of PNG images played in name order as an animation
+    pub path: PathBuf,
+    /// How long each frame of an animated wallpaper is shown
+    pub frame_duration: Duration,
+    pub mode: WallpaperMode,
+    /// Color around the image, the background color is used when unset
+    pub fill_color: Option<[f32; 4]>,
+}
+
+/// Output that new windows are opened on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NewWindowOutput {
+    Primary,
+    /// Output of the focused window, or the one under the pointer
+    Focused,
+}
+
+/// What happens to windows opened in kiosk mode while the app already has one
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KioskWindowPolicy {
+    /// New windows are made fullscreen on top of the previous ones
+    Fullscreen,
+    /// New windows are closed right away
+    Block,
+}
+
+#[derive(Debug, Clone)]
+pub struct KioskConfig {
+    /// Shell command of the kiosk app, spawned once the compositor is running
+    pub command: String,
+    pub window_policy: KioskWindowPolicy,
+    /// Start the app again when it exits, instead of closing the compositor
+    pub relaunch: bool,
+    /// Actions that keybinds are still allowed to run
+    pub allowed_actions: Vec<Action>,
+}
+
+/// Compositor configuration
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// Raise windows to the top of the stack when they get clicked
+    pub raise_on_click: bool,
+    /// Dragging a maximized or snapped window gives it its floating geometry back, with the
+    /// grabbed spot staying under the pointer
+    pub demaximize_on_move: bool,
+    /// Dragging a window to the edges of an output snaps it on release, `None` disables it
+    pub edge_snap: Option<EdgeSnap>,
+    pub focus_mode: FocusMode,
+    pub layer_pointer_passthrough: LayerPassthrough,
+    /// Hold back click focus and raise until the button is held this long, or released without
+    /// dragging, so clicks that start a drag don't reorder the windows
+    pub click_focus_delay: Option<Duration>,
+    /// Name of the primary output, like `eDP-1`, the first output is used when unset or missing
+    pub primary_output: Option<String>,
+    pub new_window_output: NewWindowOutput,
+    /// Color drawn where there is nothing else
+    pub background_color: [f32; 4],
+    /// Wallpaper of the outputs that have no wallpaper of their own
+    pub wallpaper: Option<WallpaperConfig>,
+    /// Wallpapers by output name
+    pub output_wallpapers: HashMap<String, WallpaperConfig>,
+    /// Keep the last disconnected output as a virtual output, so clients keep a place for their
+    /// windows until an output gets connected again. Nothing is rendered in the meantime.
+    pub virtual_output_fallback: bool,
+    /// Where floating windows go when the mode of their output changes
+    pub output_resize_placement: OutputResizePlacement,
+    /// Non-desktop connectors (like VR headsets) to use as regular outputs anyway, by name
+    pub non_desktop_outputs: Vec<String>,
+    /// Overscan compensation by output name, windows are kept inside the inset area
+    pub output_overscan: HashMap<String, Overscan>,
+    /// Opacity of the shade drawn over the outputs without keyboard focus, `None` disables it
+    pub dim_inactive_outputs: Option<f32>,
+    /// How long closed windows take to fade out, `None` removes them right away
+    pub close_animation: Option<Duration>,
+    /// Offset between windows arranged into a cascade
+    pub cascade_step: i32,
+    /// Delay before a held key starts repeating, in milliseconds
+    pub key_repeat_delay: u64,
+    /// Key repeats per second
+    pub key_repeat_rate: u64,
+    /// Follow the ambient light sensor with the brightness of internal panels
+    pub auto_brightness: bool,
+    pub keybinds: Vec<Keybind>,
+    /// Bindings of clicks and scrolls, the client only gets the ones that don't match
+    pub pointer_binds: Vec<PointerBind>,
+    /// How long a key sequence waits for its next key
+    pub key_sequence_timeout: Duration,
+    /// App ids of windows that only close when the close action runs twice in a row, for apps
+    /// that don't guard unsaved work themselves
+    pub confirm_close: Vec<String>,
+    /// How long a close that needs confirmation waits for the close action to run again
+    pub close_confirm_timeout: Duration,
+    /// How long a quit that needs confirmation waits for the quit action to run again
+    pub quit_confirm_timeout: Duration,
+    /// Forward a key that doesn't continue the awaited sequence to the client, instead of
+    /// dropping it
+    pub forward_unmatched_sequence_key: bool,
+    /// Key that breaks every grab and shortcut inhibitor, it bypasses keybinds and inhibitors
+    pub grab_break_key: Option<(Modifiers, u32)>,
+    /// Kiosk mode, `None` for a regular session
+    pub kiosk: Option<KioskConfig>,
+    /// Log the duration of commits, renders and layer arranges at trace level
+    pub log_timings: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            raise_on_click: true,
+            demaximize_on_move: true,
+            edge_snap: Some(EdgeSnap {
+                edge: 8,
+                corner: 64,
+            }),
+            focus_mode: FocusMode::ClickToFocus,
+            layer_pointer_passthrough: LayerPassthrough::Never,
+            click_focus_delay: None,
+            primary_output: None,
+            new_window_output: NewWindowOutput::Focused,
+            background_color: [0.1, 0.1, 0.1, 1.0],
+            wallpaper: None,
+            output_wallpapers: HashMap::new(),
+            virtual_output_fallback: true,
+            output_resize_placement: OutputResizePlacement::Absolute,
+            non_desktop_outputs: Vec::new(),
+            output_overscan: HashMap::new(),
+            dim_inactive_outputs: None,
+            close_animation: Some(Duration::from_millis(150)),
+            cascade_step: 32,
+            key_repeat_delay: 200,
+            key_repeat_rate: 25,
+            auto_brightness: false,
+            keybinds: vec![
+                Keybind::new(
+                    Modifiers::ALT,
+                    xkb::KEY_t,
+                    Action::Spawn("weston-terminal".into()),
+                ),
+                Keybind::new(
+                    Modifiers::ALT,
+                    xkb::KEY_g,
+                    Action::Spawn("gtk4-demo".into()),
+                ),
+                Keybind::new(Modifiers::ALT, xkb::KEY_c, Action::CascadeWindows),
+                Keybind::new(
+                    Modifiers::ALT | Modifiers::SHIFT,
+                    xkb::KEY_C,
+                    Action::TileWindowsGrid,
+                ),
+                Keybind::new(
+                    Modifiers::ALT,
+                    xkb::KEY_Left,
+                    Action::MoveWindow {
+                        dx: Length::Px(-32),
+                        dy: Length::Px(0),
+                    },
+                ),
+                Keybind::new(
+                    Modifiers::ALT,
+                    xkb::KEY_Right,
+                    Action::MoveWindow {
+                        dx: Length::Px(32),
+                        dy: Length::Px(0),
+                    },
+                ),
+                Keybind::new(
+                    Modifiers::ALT,
+                    xkb::KEY_Up,
+                    Action::MoveWindow {
+                        dx: Length::Px(0),
+                        dy: Length::Px(-32),
+                    },
+                ),
+                Keybind::new(
+                    Modifiers::ALT,
+                    xkb::KEY_Down,
+                    Action::MoveWindow {
+                        dx: Length::Px(0),
+                        dy: Length::Px(32),
+                    },
+                ),
+                Keybind::new(
+                    Modifiers::ALT | Modifiers::SHIFT,
+                    xkb::KEY_Left,
+                    Action::ResizeWindow {
+                        dx: Length::Px(-32),
+                        dy: Length::Px(0),
+                    },
+                ),
+                Keybind::new(
+                    Modifiers::ALT | Modifiers::SHIFT,
+                    xkb::KEY_Right,
+                    Action::ResizeWindow {
+                        dx: Length::Px(32),
+                        dy: Length::Px(0),
+                    },
+                ),
+                Keybind::new(
+                    Modifiers::ALT | Modifiers::SHIFT,
+                    xkb::KEY_Up,
+                    Action::ResizeWindow {
+                        dx: Length::Px(0),
+                        dy: Length::Px(-32),
+                    },
+                ),
+                Keybind::new(
+                    Modifiers::ALT | Modifiers::SHIFT,
+                    xkb::KEY_Down,
+                    Action::ResizeWindow {
+                        dx: Length::Px(0),
+                        dy: Length::Px(32),
+                    },
+                ),
+                Keybind::new(Modifiers::ALT, xkb::KEY_Home, Action::CenterWindow),
+                Keybind::new(Modifiers::ALT, xkb::KEY_F4, Action::CloseWindow),
+                Keybind::new(Modifiers::ALT, xkb::KEY_Tab, Action::FocusNextWindow),
+                // Shift turns Tab into ISO_Left_Tab
+                Keybind::new(
+                    Modifiers::ALT | Modifiers::SHIFT,
+                    xkb::KEY_ISO_Left_Tab,
+                    Action::FocusPreviousWindow,
+                ),
+                Keybind::new(
+                    Modifiers::LOGO,
+                    xkb::KEY_Escape,
+                    Action::ReleaseShortcutsInhibitor,
+                ),
+                Keybind::new(
+                    Modifiers::CTRL | Modifiers::ALT,
+                    xkb::KEY_BackSpace,
+                    Action::Quit { confirm: true },
+                ),
+                Keybind::new(
+                    Modifiers::empty(),
+                    xkb::KEY_XF86MonBrightnessUp,
+                    Action::ChangeBrightness { delta: 5 },
+                ),
+                Keybind::new(
+                    Modifiers::empty(),
+                    xkb::KEY_XF86MonBrightnessDown,
+                    Action::ChangeBrightness { delta: -5 },
+                ),
+            ],
+            pointer_binds: Vec::new(),
+            key_sequence_timeout: Duration::from_secs(2),
+            confirm_close: Vec::new(),
+            close_confirm_timeout: Duration::from_secs(2),
+            quit_confirm_timeout: Duration::from_secs(2),
+            forward_unmatched_sequence_key: false,
+            grab_break_key: Some((Modifiers::CTRL | Modifiers::ALT, xkb::KEY_Escape)),
+            kiosk: None,
+            log_timings: false,
+        }
+    }
+}
+
+impl Config {
+    /// Wallpaper of the output with the given name
+    pub fn wallpaper_for(&self, output_name: &str) -> Option<&WallpaperConfig> {
+        self.output_wallpapers
+            .get(output_name)
+            .or(self.wallpaper.as_ref())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keysym_bind_needs_same_modifiers() {
+        let bind = Keybind::new(Modifiers::ALT, xkb::KEY_Return, Action::CenterWindow);
+
+        assert!(bind.matches_key(Modifiers::ALT, 36, xkb::KEY_Return, &[xkb::KEY_Return]));
+        assert!(!bind.matches_key(Modifiers::LOGO, 36, xkb::KEY_Return, &[xkb::KEY_Return]));
+        assert!(!bind.matches_key(
+            Modifiers::ALT | Modifiers::SHIFT,
+            36,
+            xkb::KEY_Return,
+            &[xkb::KEY_Return]
+        ));
+        assert!(!bind.matches_key(Modifiers::ALT, 23, xkb::KEY_Tab, &[xkb::KEY_Tab]));
+    }
+
+    #[test]
+    fn shifted_keysym_bind_ignores_shift() {
+        let bind = Keybind::new(Modifiers::LOGO, xkb::KEY_exclam, Action::CenterWindow);
+
+        // `exclam` is on the shifted level of the 1 key of a US layout
+        assert!(bind.matches_key(
+            Modifiers::LOGO | Modifiers::SHIFT,
+            10,
+            xkb::KEY_exclam,
+            &[xkb::KEY_1]
+        ));
+        assert!(!bind.matches_key(Modifiers::LOGO, 10, xkb::KEY_1, &[xkb::KEY_1]));
+        assert!(!bind.matches_key(
+            Modifiers::LOGO | Modifiers::CTRL | Modifiers::SHIFT,
+            10,
+            xkb::KEY_exclam,
+            &[xkb::KEY_1]
+        ));
+    }
+
+    #[test]
+    fn keycode_bind_ignores_keysym() {
+        // evdev KEY_Q, the a of an AZERTY layout
+        let bind = Keybind::keycode(Modifiers::LOGO, 16, Action::CenterWindow);
+
+        assert!(bind.matches_key(Modifiers::LOGO, 24, xkb::KEY_a, &[xkb::KEY_a]));
+        assert!(!bind.matches_key(Modifiers::LOGO, 16, xkb::KEY_q, &[xkb::KEY_q]));
+        assert!(!bind.matches_key(Modifiers::empty(), 24, xkb::KEY_a, &[xkb::KEY_a]));
+    }
+}
@@ -1,3 +1,4 @@
 pub mod output;
 pub mod seat;
 pub mod surface;
+pub mod window;
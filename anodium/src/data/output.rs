@@ -1,10 +1,27 @@
-use smithay::output::Output;
+use std::{
+    cell::{Cell, RefCell},
+    path::Path,
+    time::Duration,
+};
+
+use smithay::{
+    backend::renderer::gles2::{Gles2Renderer, Gles2Texture},
+    output::Output,
+    reexports::wayland_server::backend::GlobalId,
+};
+
+use crate::{config::Overscan, wallpaper::WallpaperTexture};
 
 #[derive(Default, Debug)]
 pub struct OutputState {
     fps: fps_ticker::Fps,
+    dim_texture: RefCell<Option<Gles2Texture>>,
+    snap_preview_texture: RefCell<Option<Gles2Texture>>,
+    wallpaper: RefCell<Option<WallpaperTexture>>,
+    overscan: Cell<Option<Overscan>>,
+    global: RefCell<Option<GlobalId>>,
+    is_virtual: Cell<bool>,
 }
-
 impl OutputState {
     pub fn for_output(seat: &Output) -> &Self {
         seat.user_data().insert_if_missing(Self::default);
@@ -14,4 +31,73 @@ impl OutputState {
     pub fn fps_tick(&self) {
         self.fps.tick();
     }
+
+    /// Overscan compensation of the output, starts out as configured and can be changed at runtime
+    pub fn overscan(&self) -> Option<Overscan> {
+        self.overscan.get()
+    }
+
+    pub fn set_overscan(&self, overscan: Option<Overscan>) {
+        self.overscan.set(overscan);
+    }
+
+    /// Global the output is advertised to clients with
+    pub fn set_global(&self, global: GlobalId) {
+        *self.global.borrow_mut() = Some(global);
+    }
+
+    pub fn take_global(&self) -> Option<GlobalId> {
+        self.global.borrow_mut().take()
+    }
+
+    /// Output got disconnected, but is kept around since no other output is left
+    pub fn is_virtual(&self) -> bool {
+        self.is_virtual.get()
+    }
+
+    pub fn set_virtual(&self, is_virtual: bool) {
+        self.is_virtual.set(is_virtual);
+    }
+
+    /// Texture used to dim the output, created on first use
+    pub fn dim_texture(
+        &self,
+        create: impl FnOnce() -> Option<Gles2Texture>,
+    ) -> Option<Gles2Texture> {
+        let mut texture = self.dim_texture.borrow_mut();
+        if texture.is_none() {
+            *texture = create();
+        }
+        texture.clone()
+    }
+
+    /// Texture of the edge snap preview, created on first use
+    pub fn snap_preview_texture(
+        &self,
+        create: impl FnOnce() -> Option<Gles2Texture>,
+    ) -> Option<Gles2Texture> {
+        let mut texture = self.snap_preview_texture.borrow_mut();
+        if texture.is_none() {
+            *texture = create();
+        }
+        texture.clone()
+    }
+
+    /// Drop the cached textures, they get created again on their next use
+    pub fn drop_textures(&self) {
+        self.dim_texture.borrow_mut().take();
+        self.snap_preview_texture.borrow_mut().take();
+        self.wallpaper.borrow_mut().take();
+    }
+
+    /// Current wallpaper frame, reloaded when the file changes
+    pub fn wallpaper(
+        &self,
+        renderer: &mut Gles2Renderer,
+        path: &Path,
+        frame_duration: Duration,
+        elapsed: Duration,
+    ) -> Option<(Gles2Texture, bool)> {
+        WallpaperTexture::get(&self.wallpaper, renderer, path, frame_duration, elapsed)
+    }
 }
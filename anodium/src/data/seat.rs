@@ -6,6 +6,7 @@ use std::{
 use smithay::{
     backend::input::KeyState,
     input::Seat,
+    reexports::wayland_server::protocol::wl_surface::WlSurface,
     utils::{Logical, Point},
 };
 
@@ -14,7 +15,13 @@ use crate::State;
 #[derive(Debug, Default)]
 pub struct SeatState {
     pointer_pos: Cell<Point<f64, Logical>>,
+    /// Surface the pointer was last sent to, with its location
+    pointer_focus: RefCell<Option<(WlSurface, Point<i32, Logical>)>>,
+    /// Time of the last input event, synthetic events reuse it to keep timestamps monotonic
+    last_event_time: Cell<u32>,
     pressed_keys: RefCell<HashSet<u32>>,
+    /// Buttons whose press triggered a pointer bind, their release is not forwarded either
+    bound_buttons: RefCell<HashSet<u32>>,
 }
 
 impl SeatState {
@@ -31,6 +38,22 @@ impl SeatState {
         self.pointer_pos.set(pointer_pos);
     }
 
+    pub fn pointer_focus(&self) -> Option<(WlSurface, Point<i32, Logical>)> {
+        self.pointer_focus.borrow().clone()
+    }
+
+    pub fn set_pointer_focus(&self, focus: Option<(WlSurface, Point<i32, Logical>)>) {
+        *self.pointer_focus.borrow_mut() = focus;
+    }
+
+    pub fn last_event_time(&self) -> u32 {
+        self.last_event_time.get()
+    }
+
+    pub fn set_last_event_time(&self, time: u32) {
+        self.last_event_time.set(time);
+    }
+
     pub fn update_pressed_keys(&self, keysym: u32, state: KeyState) {
         if let KeyState::Pressed = state {
             self.pressed_keys.borrow_mut().insert(keysym);
@@ -42,4 +65,13 @@ impl SeatState {
     pub fn is_key_pressed(&self, keysym: u32) -> bool {
         self.pressed_keys.borrow().get(&keysym).is_some()
     }
+
+    pub fn bind_button(&self, button: u32) {
+        self.bound_buttons.borrow_mut().insert(button);
+    }
+
+    /// Forget about the bound button, returns `true` if the button was bound
+    pub fn release_bound_button(&self, button: u32) -> bool {
+        self.bound_buttons.borrow_mut().remove(&button)
+    }
 }
@@ -1,14 +1,20 @@
-use std::cell::RefCell;
+use std::cell::Cell;
 
 use smithay::{
+    backend::renderer::utils::RendererSurfaceStateUserData,
     reexports::{
         wayland_protocols::xdg::shell::server::xdg_toplevel,
-        wayland_server::protocol::wl_surface::WlSurface,
+        wayland_server::{protocol::wl_surface::WlSurface, DisplayHandle},
+    },
+    utils::{Logical, Rectangle, Size},
+    wayland::{
+        compositor::{self, Cacheable},
+        shell::xdg::SurfaceCachedState,
     },
-    utils::{Logical, Rectangle},
-    wayland::compositor,
 };
 
+use crate::protocols::content_type_v1::wp_content_type_v1;
+
 bitflags::bitflags! {
     pub struct ResizeEdge: u32 {
         const TOP          = 0b0001;
@@ -63,10 +69,15 @@ impl ResizeSurfaceState {
         F: FnOnce(&mut Self) -> T,
     {
         compositor::with_states(surface, |states| {
-            states.data_map.insert_if_missing(RefCell::<Self>::default);
-            let state = states.data_map.get::<RefCell<Self>>().unwrap();
+            // The state is `Copy`, so a `Cell` is enough, no borrow tracking on every commit
+            states.data_map.insert_if_missing(Cell::<Self>::default);
+            let cell = states.data_map.get::<Cell<Self>>().unwrap();
+
+            let mut state = cell.get();
+            let ret = cb(&mut state);
+            cell.set(state);
 
-            cb(&mut *state.borrow_mut())
+            ret
         })
     }
 
@@ -89,3 +100,120 @@ impl ResizeSurfaceState {
         }
     }
 }
+
+/// Check if the surface has a buffer attached
+///
+/// Panic free version of `with_renderer_surface_state`, surfaces that did not go through
+/// `on_commit_buffer_handler` yet are treated as not having a buffer.
+pub fn is_buffer_attached(surface: &WlSurface) -> bool {
+    compositor::with_states(surface, |states| {
+        states
+            .data_map
+            .get::<RendererSurfaceStateUserData>()
+            .map(|data| data.borrow().wl_buffer().is_some())
+            .unwrap_or(false)
+    })
+}
+
+/// Min and max size requested by the client
+///
+/// Unset limits are normalized, so the result can be used for clamping directly.
+pub fn size_constraints(surface: &WlSurface) -> (Size<i32, Logical>, Size<i32, Logical>) {
+    let (min_size, max_size) = compositor::with_states(surface, |states| {
+        let data = states.cached_state.current::<SurfaceCachedState>();
+        (data.min_size, data.max_size)
+    });
+
+    let min_width = min_size.w.max(1);
+    let min_height = min_size.h.max(1);
+
+    let max_width = (max_size.w == 0).then(i32::max_value).unwrap_or(max_size.w);
+    let max_height = (max_size.h == 0).then(i32::max_value).unwrap_or(max_size.h);
+
+    (
+        (min_width, min_height).into(),
+        (max_width.max(min_width), max_height.max(min_height)).into(),
+    )
+}
+
+/// Kind of content shown by the surface, as hinted by the client through `wp_content_type_v1`
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ContentType {
+    None,
+    Photo,
+    Video,
+    Game,
+}
+
+impl Default for ContentType {
+    fn default() -> Self {
+        ContentType::None
+    }
+}
+
+impl From<wp_content_type_v1::Type> for ContentType {
+    fn from(x: wp_content_type_v1::Type) -> Self {
+        match x {
+            wp_content_type_v1::Type::Photo => Self::Photo,
+            wp_content_type_v1::Type::Video => Self::Video,
+            wp_content_type_v1::Type::Game => Self::Game,
+            _ => Self::None,
+        }
+    }
+}
+
+/// Double buffered content type hint of the surface
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ContentTypeCachedState {
+    pub content_type: ContentType,
+}
+
+impl Cacheable for ContentTypeCachedState {
+    fn commit(&mut self, _dh: &DisplayHandle) -> Self {
+        *self
+    }
+
+    fn merge_into(self, into: &mut Self, _dh: &DisplayHandle) {
+        *into = self;
+    }
+}
+
+/// Content type hint of the surface, as of the last commit
+pub fn content_type(surface: &WlSurface) -> ContentType {
+    compositor::with_states(surface, |states| {
+        states
+            .cached_state
+            .current::<ContentTypeCachedState>()
+            .content_type
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn commit_during_resize_keeps_resizing() {
+        let initial_rect = Rectangle::from_loc_and_size((0, 0), (100, 100));
+        let mut state = ResizeSurfaceState::Resizing {
+            edges: ResizeEdge::TOP,
+            initial_rect,
+        };
+
+        assert_eq!(state.commit(), Some((ResizeEdge::TOP, initial_rect)));
+        assert_eq!(state.commit(), Some((ResizeEdge::TOP, initial_rect)));
+    }
+
+    #[test]
+    fn last_commit_ends_resize() {
+        let initial_rect = Rectangle::from_loc_and_size((0, 0), (100, 100));
+        let mut state = ResizeSurfaceState::WaitingForLastCommit {
+            edges: ResizeEdge::LEFT,
+            initial_rect,
+        };
+
+        assert_eq!(state.commit(), Some((ResizeEdge::LEFT, initial_rect)));
+        assert_eq!(state, ResizeSurfaceState::Idle);
+        assert_eq!(state.commit(), None);
+    }
+}
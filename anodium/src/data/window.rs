@@ -0,0 +1,96 @@
+use std::{
+    cell::{Cell, RefCell},
+    time::Duration,
+};
+
+use smithay::{
+    desktop::Window,
+    utils::{Logical, Rectangle},
+};
+
+use crate::{closing::WindowSnapshot, config::SnapRegion};
+
+/// How the window is sized and placed
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum WindowMode {
+    #[default]
+    Floating,
+    /// Filling the usable area of its output
+    Maximized,
+    /// Covering its whole output
+    Fullscreen,
+    /// Snapped to a region of the usable area of its output, `step` counts repeated snaps
+    Snapped { region: SnapRegion, step: usize },
+}
+
+#[derive(Default, Debug)]
+pub struct WindowState {
+    last_frame: Cell<Option<Duration>>,
+    unmanaged: Cell<bool>,
+    mode: Cell<WindowMode>,
+    restore_geometry: Cell<Option<Rectangle<i32, Logical>>>,
+    snapshot: RefCell<Option<WindowSnapshot>>,
+}
+
+impl WindowState {
+    pub fn for_window(window: &Window) -> &Self {
+        window.user_data().insert_if_missing(Self::default);
+        window.user_data().get::<Self>().unwrap()
+    }
+
+    /// Unmanaged windows (like xwayland menus and tooltips) position themselves,
+    /// and are left alone by window arrangement
+    pub fn is_unmanaged(&self) -> bool {
+        self.unmanaged.get()
+    }
+
+    pub fn set_unmanaged(&self, unmanaged: bool) {
+        self.unmanaged.set(unmanaged);
+    }
+
+    pub fn mode(&self) -> WindowMode {
+        self.mode.get()
+    }
+
+    pub fn set_mode(&self, mode: WindowMode) {
+        self.mode.set(mode);
+    }
+
+    /// Floating geometry to go back to when the window leaves maximized or fullscreen mode
+    pub fn restore_geometry(&self) -> Option<Rectangle<i32, Logical>> {
+        self.restore_geometry.get()
+    }
+
+    pub fn set_restore_geometry(&self, geometry: Option<Rectangle<i32, Logical>>) {
+        self.restore_geometry.set(geometry);
+    }
+
+    /// Keep the last frame of the window, for the close animation
+    pub fn set_snapshot(&self, snapshot: Option<WindowSnapshot>) {
+        *self.snapshot.borrow_mut() = snapshot;
+    }
+
+    pub fn take_snapshot(&self) -> Option<WindowSnapshot> {
+        self.snapshot.borrow_mut().take()
+    }
+
+    /// Check if enough time passed since the last frame callback, and if so mark a new one as sent
+    ///
+    /// `time` is the compositor clock, `interval` is the refresh interval of the output
+    /// the window is presented on.
+    pub fn frame_due(&self, time: Duration, interval: Duration) -> bool {
+        // Allow for a bit of vblank jitter, otherwise a slightly early vblank would halve the client framerate
+        let slack = interval / 10;
+
+        let due = match self.last_frame.get() {
+            Some(last) => time.saturating_sub(last) + slack >= interval,
+            None => true,
+        };
+
+        if due {
+            self.last_frame.set(Some(time));
+        }
+
+        due
+    }
+}
@@ -0,0 +1,102 @@
+//! Dimming of the outputs that don't hold the keyboard focus
+
+use smithay::{
+    backend::renderer::{
+        gles2::{Gles2Error, Gles2Frame, Gles2Renderer, Gles2Texture},
+        Frame, ImportMem,
+    },
+    desktop::space::{RenderElement, RenderZindex, SpaceOutputTuple},
+    output::Output,
+    utils::{Buffer, Logical, Physical, Point, Rectangle, Scale, Transform},
+};
+
+use crate::{data::output::OutputState, handlers::DIM_ELEMENT_ID};
+
+/// Translucent black quad covering a whole output
+///
+/// It is stacked right below the overlay layer, so overlay surfaces and the cursor stay undimmed.
+#[derive(Debug, Clone)]
+pub struct DimElement {
+    texture: Gles2Texture,
+    geometry: Rectangle<i32, Logical>,
+    opacity: f32,
+}
+
+impl DimElement {
+    pub fn new(
+        renderer: &mut Gles2Renderer,
+        output: &Output,
+        geometry: Rectangle<i32, Logical>,
+        opacity: f32,
+    ) -> Option<Self> {
+        let texture = OutputState::for_output(output).dim_texture(|| {
+            renderer
+                .import_memory(&[0, 0, 0, 255], (1, 1).into(), false)
+                .ok()
+        })?;
+
+        Some(Self {
+            texture,
+            // Custom elements are positioned relative to the output
+            geometry: Rectangle::from_loc_and_size((0, 0), geometry.size),
+            opacity: opacity.clamp(0.0, 1.0),
+        })
+    }
+}
+
+impl RenderElement<Gles2Renderer> for DimElement {
+    fn id(&self) -> usize {
+        // Only one dim element per output
+        DIM_ELEMENT_ID
+    }
+
+    fn location(&self, scale: impl Into<Scale<f64>>) -> Point<f64, Physical> {
+        self.geometry.loc.to_f64().to_physical(scale)
+    }
+
+    fn geometry(&self, scale: impl Into<Scale<f64>>) -> Rectangle<i32, Physical> {
+        self.geometry.to_physical_precise_round(scale)
+    }
+
+    fn accumulated_damage(
+        &self,
+        _scale: impl Into<Scale<f64>>,
+        _: Option<SpaceOutputTuple<'_, '_>>,
+    ) -> Vec<Rectangle<i32, Physical>> {
+        // The quad never changes, the space damages it when it appears or goes away
+        vec![]
+    }
+
+    fn draw(
+        &self,
+        _renderer: &mut Gles2Renderer,
+        frame: &mut Gles2Frame,
+        scale: impl Into<Scale<f64>>,
+        location: Point<f64, Physical>,
+        damage: &[Rectangle<i32, Physical>],
+        _log: &slog::Logger,
+    ) -> Result<(), Gles2Error> {
+        let dst =
+            Rectangle::from_loc_and_size(location, self.geometry.size.to_f64().to_physical(scale));
+
+        frame.render_texture_from_to(
+            &self.texture,
+            Rectangle::<f64, Buffer>::from_loc_and_size((0.0, 0.0), (1.0, 1.0)),
+            dst,
+            damage,
+            Transform::Normal,
+            self.opacity,
+        )
+    }
+
+    fn opaque_regions(
+        &self,
+        _scale: impl Into<Scale<f64>>,
+    ) -> Option<Vec<Rectangle<i32, Physical>>> {
+        None
+    }
+
+    fn z_index(&self) -> u8 {
+        RenderZindex::Overlay as u8 - 1
+    }
+}
@@ -0,0 +1,175 @@
+//! Snapping windows by dragging them to the edges of an output
+//!
+//! While a move grab hovers a trigger zone, the region the window would snap to is shown
+//! as a translucent preview. The window only snaps once the grab is released there.
+
+use smithay::{
+    backend::renderer::{
+        gles2::{Gles2Error, Gles2Frame, Gles2Renderer, Gles2Texture},
+        Frame, ImportMem,
+    },
+    desktop::{
+        space::{RenderElement, RenderZindex, SpaceOutputTuple},
+        Space, Window,
+    },
+    output::Output,
+    utils::{Buffer, Logical, Physical, Point, Rectangle, Scale, Transform},
+};
+
+use crate::{
+    config::{EdgeSnap, SnapRegion},
+    data::output::OutputState,
+    handlers::SNAP_PREVIEW_ELEMENT_ID,
+    positioning, State,
+};
+
+/// Opacity of the region preview
+const PREVIEW_OPACITY: f32 = 0.3;
+
+/// Region of the output under the pointer that a window dropped there snaps to
+///
+/// Edges shared with another output don't trigger, so windows can still be dragged across.
+fn region_under(
+    space: &Space,
+    pointer: Point<f64, Logical>,
+    zones: EdgeSnap,
+) -> Option<(Output, SnapRegion)> {
+    let output = space.output_under(pointer).next()?;
+    let geo = space.output_geometry(output)?.to_f64();
+
+    let edge = zones.edge as f64;
+    let corner = zones.corner as f64;
+
+    let right_x = geo.loc.x + geo.size.w;
+    let bottom_y = geo.loc.y + geo.size.h;
+
+    let is_free = |x: f64, y: f64| space.output_under(Point::from((x, y))).next().is_none();
+
+    let left = pointer.x < geo.loc.x + edge && is_free(geo.loc.x - 1.0, pointer.y);
+    let right = pointer.x >= right_x - edge && is_free(right_x, pointer.y);
+    let top = pointer.y < geo.loc.y + edge && is_free(pointer.x, geo.loc.y - 1.0);
+
+    let near_top = pointer.y < geo.loc.y + corner;
+    let near_bottom = pointer.y >= bottom_y - corner;
+
+    let region = match (left, right) {
+        (true, _) if near_top => SnapRegion::TopLeftQuarter,
+        (true, _) if near_bottom => SnapRegion::BottomLeftQuarter,
+        (true, _) => SnapRegion::LeftHalf,
+        (_, true) if near_top => SnapRegion::TopRightQuarter,
+        (_, true) if near_bottom => SnapRegion::BottomRightQuarter,
+        (_, true) => SnapRegion::RightHalf,
+        _ if top => SnapRegion::Maximized,
+        _ => return None,
+    };
+
+    Some((output.clone(), region))
+}
+
+impl State {
+    /// Update the snap target of a window dragged to the pointer location
+    pub fn update_edge_snap(&mut self, pointer: Point<f64, Logical>) {
+        self.edge_snap = self
+            .config
+            .edge_snap
+            .and_then(|zones| region_under(&self.space, pointer, zones));
+    }
+
+    /// Snap the dragged window to the hovered region, if any, once the grab is released
+    pub fn finish_edge_snap(&mut self, window: &Window) {
+        if let Some((output, region)) = self.edge_snap.take() {
+            positioning::snap_window_on(&mut self.space, window, &output, region);
+        }
+    }
+
+    /// Preview of the region the dragged window would snap to, on the given output
+    pub fn edge_snap_element(
+        &self,
+        renderer: &mut Gles2Renderer,
+        output: &Output,
+    ) -> Option<SnapPreviewElement> {
+        let (snap_output, region) = self.edge_snap.as_ref()?;
+        if snap_output != output {
+            return None;
+        }
+
+        let output_geo = self.space.output_geometry(output)?;
+        let mut geometry = positioning::snap_region_rect(&self.space, output, *region)?;
+        // Custom elements are positioned relative to the output
+        geometry.loc -= output_geo.loc;
+
+        let texture = OutputState::for_output(output).snap_preview_texture(|| {
+            renderer
+                .import_memory(&[255, 255, 255, 255], (1, 1).into(), false)
+                .ok()
+        })?;
+
+        Some(SnapPreviewElement { texture, geometry })
+    }
+}
+
+/// Translucent quad covering the region a dragged window would snap to
+///
+/// It is stacked right above the windows, panels on the top layer stay visible.
+#[derive(Debug, Clone)]
+pub struct SnapPreviewElement {
+    texture: Gles2Texture,
+    geometry: Rectangle<i32, Logical>,
+}
+
+impl RenderElement<Gles2Renderer> for SnapPreviewElement {
+    fn id(&self) -> usize {
+        // Only one preview per output
+        SNAP_PREVIEW_ELEMENT_ID
+    }
+
+    fn location(&self, scale: impl Into<Scale<f64>>) -> Point<f64, Physical> {
+        self.geometry.loc.to_f64().to_physical(scale)
+    }
+
+    fn geometry(&self, scale: impl Into<Scale<f64>>) -> Rectangle<i32, Physical> {
+        self.geometry.to_physical_precise_round(scale)
+    }
+
+    fn accumulated_damage(
+        &self,
+        _scale: impl Into<Scale<f64>>,
+        _: Option<SpaceOutputTuple<'_, '_>>,
+    ) -> Vec<Rectangle<i32, Physical>> {
+        // The quad never changes, the space damages it when it moves, appears or goes away
+        vec![]
+    }
+
+    fn draw(
+        &self,
+        _renderer: &mut Gles2Renderer,
+        frame: &mut Gles2Frame,
+        scale: impl Into<Scale<f64>>,
+        location: Point<f64, Physical>,
+        damage: &[Rectangle<i32, Physical>],
+        _log: &slog::Logger,
+    ) -> Result<(), Gles2Error> {
+        let dst =
+            Rectangle::from_loc_and_size(location, self.geometry.size.to_f64().to_physical(scale));
+
+        frame.render_texture_from_to(
+            &self.texture,
+            Rectangle::<f64, Buffer>::from_loc_and_size((0.0, 0.0), (1.0, 1.0)),
+            dst,
+            damage,
+            Transform::Normal,
+            PREVIEW_OPACITY,
+        )
+    }
+
+    fn opaque_regions(
+        &self,
+        _scale: impl Into<Scale<f64>>,
+    ) -> Option<Vec<Rectangle<i32, Physical>>> {
+        None
+    }
+
+    fn z_index(&self) -> u8 {
+        RenderZindex::Shell as u8 + 1
+    }
+}
@@ -0,0 +1,142 @@
+//! Most recently used order of the windows, for cycling the keyboard focus through them
+//!
+//! While a cycle runs, the focused windows don't reorder the history, so repeated cycling
+//! walks through all of them. The window the cycle ends on moves to the front.
+
+use smithay::{reexports::wayland_server::protocol::wl_surface::WlSurface, utils::IsAlive};
+
+use crate::{data::window::WindowState, State};
+
+/// Move the entry to the front of the history
+fn promote<T: PartialEq>(history: &mut Vec<T>, entry: T) {
+    history.retain(|e| *e != entry);
+    history.insert(0, entry);
+}
+
+/// Entry following `current` in the history, or preceding it, wrapping around at the ends
+///
+/// Without a current entry, the first entry follows and the last one precedes.
+fn cycle_target<'a, T: PartialEq>(
+    history: &'a [T],
+    current: Option<&T>,
+    forward: bool,
+) -> Option<&'a T> {
+    let count = history.len();
+    if count == 0 {
+        return None;
+    }
+
+    let current = current.and_then(|current| history.iter().position(|e| e == current));
+
+    let index = match (current, forward) {
+        (Some(current), true) => (current + 1) % count,
+        (Some(current), false) => (current + count - 1) % count,
+        (None, true) => 0,
+        (None, false) => count - 1,
+    };
+
+    history.get(index)
+}
+
+impl State {
+    /// Move the window of the newly focused surface to the front of the focus history
+    pub fn record_focus(&mut self, surface: Option<&WlSurface>) {
+        // Destroyed windows would be kept alive by the history otherwise
+        self.focus_history
+            .retain(|window| window.toplevel().alive());
+
+        if self.focus_cycle.is_some() {
+            return;
+        }
+
+        if let Some(window) = surface.and_then(|surface| self.window_index.get(surface)) {
+            promote(&mut self.focus_history, window.clone());
+        }
+    }
+
+    /// Focus the next or previous window of the focus history, starting a cycle if none runs
+    pub fn cycle_focus(&mut self, forward: bool) {
+        // Destroyed and unmapped windows drop out, windows that never had focus go last
+        let space = &self.space;
+        self.focus_history
+            .retain(|window| window.toplevel().alive() && space.window_location(window).is_some());
+
+        let unfocused: Vec<_> = self
+            .space
+            .windows()
+            .filter(|window| !WindowState::for_window(window).is_unmanaged())
+            .filter(|window| !self.focus_history.contains(window))
+            .cloned()
+            .collect();
+        self.focus_history.extend(unfocused);
+
+        let current = self.focus_cycle.clone().or_else(|| self.focused_window());
+        let target = cycle_target(&self.focus_history, current.as_ref(), forward).cloned();
+
+        self.focus_cycle = target.clone();
+
+        if let Some(window) = target {
+            self.focus_window(&window);
+        }
+    }
+
+    /// End the focus cycle, the window it ended on moves to the front of the focus history
+    pub fn finish_focus_cycle(&mut self) {
+        if let Some(window) = self.focus_cycle.take() {
+            // The window might have been closed during the cycle
+            if self.focus_history.contains(&window) {
+                promote(&mut self.focus_history, window);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn promote_moves_to_front() {
+        let mut history = vec![1, 2, 3];
+
+        promote(&mut history, 3);
+        assert_eq!(history, [3, 1, 2]);
+
+        promote(&mut history, 4);
+        assert_eq!(history, [4, 3, 1, 2]);
+    }
+
+    #[test]
+    fn cycle_wraps_around() {
+        let history = [1, 2, 3];
+
+        assert_eq!(cycle_target(&history, Some(&1), true), Some(&2));
+        assert_eq!(cycle_target(&history, Some(&3), true), Some(&1));
+        assert_eq!(cycle_target(&history, Some(&1), false), Some(&3));
+        assert_eq!(cycle_target(&history, Some(&2), false), Some(&1));
+    }
+
+    #[test]
+    fn cycle_without_current_starts_at_the_ends() {
+        let history = [1, 2, 3];
+
+        assert_eq!(cycle_target(&history, None, true), Some(&1));
+        assert_eq!(cycle_target(&history, None, false), Some(&3));
+        // Closed during the cycle
+        assert_eq!(cycle_target(&history, Some(&4), true), Some(&1));
+    }
+
+    #[test]
+    fn cycle_of_single_window_stays_on_it() {
+        assert_eq!(cycle_target(&[1], Some(&1), true), Some(&1));
+        assert_eq!(cycle_target(&[1], Some(&1), false), Some(&1));
+    }
+
+    #[test]
+    fn cycle_of_empty_history_has_no_target() {
+        let history: [u32; 0] = [];
+
+        assert_eq!(cycle_target(&history, None, true), None);
+        assert_eq!(cycle_target(&history, Some(&1), false), None);
+    }
+}
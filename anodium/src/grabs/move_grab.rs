@@ -8,7 +8,10 @@ use smithay::{
     utils::{Logical, Point},
 };
 
-use crate::State;
+use crate::{
+    data::window::{WindowMode, WindowState},
+    positioning, transient, State,
+};
 
 pub struct MoveSurfaceGrab {
     pub start_data: PointerGrabStartData<State>,
@@ -16,6 +19,32 @@ pub struct MoveSurfaceGrab {
     pub initial_window_location: Point<i32, Logical>,
 }
 
+impl MoveSurfaceGrab {
+    /// Start moving the window, maximized and snapped windows get their floating geometry back
+    /// first if configured
+    pub fn start(
+        state: &mut State,
+        start_data: PointerGrabStartData<State>,
+        window: Window,
+    ) -> Self {
+        let mode = WindowState::for_window(&window).mode();
+        if state.config.demaximize_on_move
+            && matches!(mode, WindowMode::Maximized | WindowMode::Snapped { .. })
+        {
+            positioning::restore_window_for_move(&mut state.space, &window, start_data.location);
+        }
+
+        let initial_window_location = state.space.window_location(&window).unwrap();
+        state.backend.set_cursor_shape(Some("grabbing"));
+
+        Self {
+            start_data,
+            window,
+            initial_window_location,
+        }
+    }
+}
+
 impl PointerGrab<State> for MoveSurfaceGrab {
     fn motion(
         &mut self,
@@ -28,9 +57,14 @@ impl PointerGrab<State> for MoveSurfaceGrab {
 
         let delta = event.location - self.start_data.location;
         let new_location = self.initial_window_location.to_f64() + delta;
-        state
-            .space
-            .map_window(&self.window, new_location.to_i32_round(), None, true);
+        transient::map_with_children(
+            &mut state.space,
+            &self.window,
+            new_location.to_i32_round(),
+            true,
+        );
+
+        state.update_edge_snap(event.location);
     }
 
     fn button(
@@ -47,6 +81,8 @@ impl PointerGrab<State> for MoveSurfaceGrab {
 
         if !handle.current_pressed().contains(&BTN_LEFT) {
             // No more buttons are pressed, release the grab.
+            state.finish_edge_snap(&self.window);
+            state.backend.set_cursor_shape(None);
             handle.unset_grab(state, event.serial, event.time);
         }
     }
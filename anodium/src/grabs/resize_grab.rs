@@ -1,5 +1,5 @@
 use smithay::{
-    desktop::{Kind, Space, Window, WindowSurfaceType},
+    desktop::{Kind, Space, Window},
     input::pointer::{
         AxisFrame, ButtonEvent, GrabStartData as PointerGrabStartData, MotionEvent, PointerGrab,
         PointerInnerHandle,
@@ -9,11 +9,11 @@ use smithay::{
         wayland_server::protocol::wl_surface::WlSurface,
     },
     utils::{Logical, Point, Rectangle, Size},
-    wayland::{compositor, shell::xdg::SurfaceCachedState},
 };
 
 use crate::{
-    data::surface::{ResizeEdge, ResizeSurfaceState},
+    data::surface::{self, ResizeEdge, ResizeSurfaceState},
+    window_index::WindowIndex,
     State,
 };
 
@@ -29,6 +29,7 @@ pub struct ResizeSurfaceGrab {
 
 impl ResizeSurfaceGrab {
     pub fn start(
+        state: &mut State,
         start_data: PointerGrabStartData<State>,
         window: Window,
         edges: ResizeEdge,
@@ -36,6 +37,8 @@ impl ResizeSurfaceGrab {
     ) -> Self {
         let initial_rect = initial_window_rect;
 
+        state.backend.set_cursor_shape(cursor_shape(edges));
+
         ResizeSurfaceState::for_surface(window.toplevel().wl_surface(), |state| {
             *state = ResizeSurfaceState::Resizing {
                 edges,
@@ -53,6 +56,22 @@ impl ResizeSurfaceGrab {
     }
 }
 
+/// Name of the themed cursor for resizing from the given edges
+fn cursor_shape(edges: ResizeEdge) -> Option<&'static str> {
+    let name = match edges {
+        ResizeEdge::TOP => "n-resize",
+        ResizeEdge::BOTTOM => "s-resize",
+        ResizeEdge::LEFT => "w-resize",
+        ResizeEdge::RIGHT => "e-resize",
+        ResizeEdge::TOP_LEFT => "nw-resize",
+        ResizeEdge::TOP_RIGHT => "ne-resize",
+        ResizeEdge::BOTTOM_LEFT => "sw-resize",
+        ResizeEdge::BOTTOM_RIGHT => "se-resize",
+        _ => return None,
+    };
+    Some(name)
+}
+
 impl PointerGrab<State> for ResizeSurfaceGrab {
     fn motion(
         &mut self,
@@ -84,21 +103,11 @@ impl PointerGrab<State> for ResizeSurfaceGrab {
             new_window_height = (self.initial_rect.size.h as f64 + delta.y) as i32;
         }
 
-        let (min_size, max_size) =
-            compositor::with_states(self.window.toplevel().wl_surface(), |states| {
-                let data = states.cached_state.current::<SurfaceCachedState>();
-                (data.min_size, data.max_size)
-            });
-
-        let min_width = min_size.w.max(1);
-        let min_height = min_size.h.max(1);
-
-        let max_width = (max_size.w == 0).then(i32::max_value).unwrap_or(max_size.w);
-        let max_height = (max_size.h == 0).then(i32::max_value).unwrap_or(max_size.h);
+        let (min_size, max_size) = surface::size_constraints(self.window.toplevel().wl_surface());
 
         self.last_window_size = Size::from((
-            new_window_width.max(min_width).min(max_width),
-            new_window_height.max(min_height).min(max_height),
+            new_window_width.max(min_size.w).min(max_size.w),
+            new_window_height.max(min_size.h).min(max_size.h),
         ));
 
         if let Kind::Xdg(xdg) = self.window.toplevel() {
@@ -125,6 +134,7 @@ impl PointerGrab<State> for ResizeSurfaceGrab {
 
         if !handle.current_pressed().contains(&BTN_LEFT) {
             // No more buttons are pressed, release the grab.
+            state.backend.set_cursor_shape(None);
             handle.unset_grab(state, event.serial, event.time);
 
             if let Kind::Xdg(xdg) = self.window.toplevel() {
@@ -160,46 +170,124 @@ impl PointerGrab<State> for ResizeSurfaceGrab {
 }
 
 /// Should be called on `WlSurface::commit`
-pub fn handle_commit(space: &mut Space, surface: &WlSurface) -> Option<()> {
-    let window = space
-        .window_for_surface(surface, WindowSurfaceType::TOPLEVEL)
-        .cloned()?;
-
-    let mut window_loc = space.window_location(&window)?;
-    let geometry = window.geometry();
-
-    let new_loc: Point<Option<i32>, Logical> = ResizeSurfaceState::for_surface(surface, |state| {
-        state
-            .commit()
-            .and_then(|(edges, initial_rect)| {
-                // If the window is being resized by top or left, its location must be adjusted
-                // accordingly.
-                edges.intersects(ResizeEdge::TOP_LEFT).then(|| {
-                    let new_x = edges
-                        .intersects(ResizeEdge::LEFT)
-                        .then(|| initial_rect.loc.x + (initial_rect.size.w - geometry.size.w));
-
-                    let new_y = edges
-                        .intersects(ResizeEdge::TOP)
-                        .then(|| initial_rect.loc.y + (initial_rect.size.h - geometry.size.h));
-
-                    (new_x, new_y).into()
-                })
-            })
-            .unwrap_or_default()
+pub fn handle_commit(
+    space: &mut Space,
+    window_index: &WindowIndex,
+    surface: &WlSurface,
+) -> Option<()> {
+    // Most commits are just new buffers of surfaces that are not being resized,
+    // so the window is only looked up once we know that a resize is in progress
+    let (edges, initial_rect) = ResizeSurfaceState::for_surface(surface, |state| state.commit())?;
+
+    // If the window is being resized by top or left, its location must be adjusted accordingly.
+    if !edges.intersects(ResizeEdge::TOP_LEFT) {
+        return Some(());
+    }
+
+    let window = window_index.get(surface).cloned()?;
+
+    let window_loc = space.window_location(&window)?;
+    let window_loc = resized_location(edges, initial_rect, window.geometry().size, window_loc);
+
+    // If TOP or LEFT side of the window got resized, we have to move it
+    space.map_window(&window, window_loc, None, false);
+
+    Some(())
+}
+
+/// Location of a window resized by the given edges, keeping its opposite edges in place
+fn resized_location(
+    edges: ResizeEdge,
+    initial_rect: Rectangle<i32, Logical>,
+    size: Size<i32, Logical>,
+    mut location: Point<i32, Logical>,
+) -> Point<i32, Logical> {
+    if edges.intersects(ResizeEdge::LEFT) {
+        location.x = initial_rect.loc.x + (initial_rect.size.w - size.w);
+    }
+    if edges.intersects(ResizeEdge::TOP) {
+        location.y = initial_rect.loc.y + (initial_rect.size.h - size.h);
+    }
+
+    location
+}
+
+/// End an interrupted resize, as if the button was released
+///
+/// The grab itself has to be unset by the caller.
+pub fn cancel(window: &Window) {
+    let xdg = if let Kind::Xdg(xdg) = window.toplevel() {
+        xdg
+    } else {
+        return;
+    };
+
+    let resize = ResizeSurfaceState::for_surface(xdg.wl_surface(), |state| match *state {
+        ResizeSurfaceState::Resizing {
+            edges,
+            initial_rect,
+        } => {
+            *state = ResizeSurfaceState::WaitingForLastCommit {
+                edges,
+                initial_rect,
+            };
+            true
+        }
+        _ => false,
     });
 
-    if let Some(new_x) = new_loc.x {
-        window_loc.x = new_x;
+    if resize {
+        xdg.with_pending_state(|state| {
+            state.states.unset(xdg_toplevel::State::Resizing);
+        });
+        xdg.send_configure();
     }
-    if let Some(new_y) = new_loc.y {
-        window_loc.y = new_y;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resize_by_top_left_keeps_bottom_right() {
+        let initial_rect = Rectangle::from_loc_and_size((100, 100), (400, 300));
+
+        let loc = resized_location(
+            ResizeEdge::TOP_LEFT,
+            initial_rect,
+            Size::from((300, 350)),
+            Point::from((100, 100)),
+        );
+
+        assert_eq!(loc, Point::from((200, 50)));
     }
 
-    if new_loc.x.is_some() || new_loc.y.is_some() {
-        // If TOP or LEFT side of the window got resized, we have to move it
-        space.map_window(&window, window_loc, None, false);
+    #[test]
+    fn resize_by_bottom_right_keeps_location() {
+        let initial_rect = Rectangle::from_loc_and_size((100, 100), (400, 300));
+
+        let loc = resized_location(
+            ResizeEdge::BOTTOM_RIGHT,
+            initial_rect,
+            Size::from((300, 350)),
+            Point::from((100, 100)),
+        );
+
+        assert_eq!(loc, Point::from((100, 100)));
     }
 
-    Some(())
+    #[test]
+    fn resize_by_left_keeps_vertical_location() {
+        let initial_rect = Rectangle::from_loc_and_size((100, 100), (400, 300));
+
+        // The window was moved vertically since the resize started
+        let loc = resized_location(
+            ResizeEdge::LEFT,
+            initial_rect,
+            Size::from((500, 300)),
+            Point::from((100, 120)),
+        );
+
+        assert_eq!(loc, Point::from((0, 120)));
+    }
 }
@@ -20,6 +20,8 @@ impl BackendHandler for CalloopData {
 
         #[cfg(feature = "xwayland")]
         self.state.xwayland.start(&self.state._loop_handle);
+
+        self.state.spawn_kiosk_app();
     }
 
     fn close_compositor(&mut self) {
@@ -1,7 +1,10 @@
 use smithay::{
     backend::renderer::utils::on_commit_buffer_handler,
     delegate_compositor, delegate_shm,
-    reexports::wayland_server::protocol::{wl_buffer, wl_surface::WlSurface},
+    reexports::wayland_server::{
+        protocol::{wl_buffer, wl_surface::WlSurface},
+        Resource,
+    },
     wayland::{
         buffer::BufferHandler,
         compositor::{CompositorHandler, CompositorState},
@@ -9,7 +12,8 @@ use smithay::{
     },
 };
 
-use crate::{grabs::resize_grab, on_commit::OnCommitDispatcher, xwayland, State};
+use super::layer_shell;
+use crate::{grabs::resize_grab, on_commit::OnCommitDispatcher, timing, xwayland, State};
 
 impl CompositorHandler for State {
     fn compositor_state(&mut self) -> &mut CompositorState {
@@ -17,15 +21,20 @@ impl CompositorHandler for State {
     }
 
     fn commit(&mut self, surface: &WlSurface) {
+        let start = timing::start(self.config.log_timings);
+
         on_commit_buffer_handler(surface);
 
         self.space.commit(surface);
-        resize_grab::handle_commit(&mut self.space, surface);
+        layer_shell::handle_commit(self, surface);
+        resize_grab::handle_commit(&mut self.space, &self.window_index, surface);
 
         #[cfg(feature = "xwayland")]
         xwayland::handle_commit(self, surface);
 
         OnCommitDispatcher::handle_commit(self, surface);
+
+        timing::finish(start, || format!("Committed surface {:?}", surface.id()));
     }
 }
 
@@ -0,0 +1,120 @@
+use std::cell::Cell;
+
+use smithay::{
+    reexports::wayland_server::{
+        backend::GlobalId, protocol::wl_surface::WlSurface, Client, DataInit, Dispatch,
+        DisplayHandle, GlobalDispatch, New, Resource, WEnum,
+    },
+    wayland::compositor,
+};
+
+use crate::{
+    data::surface::{ContentType, ContentTypeCachedState},
+    protocols::content_type_v1::{
+        wp_content_type_manager_v1::{self, WpContentTypeManagerV1},
+        wp_content_type_v1::{self, WpContentTypeV1},
+    },
+    State,
+};
+
+/// Marks surfaces that already have a `wp_content_type_v1` object
+#[derive(Default)]
+struct ContentTypeObject(Cell<bool>);
+
+fn content_type_object<T>(surface: &WlSurface, cb: impl FnOnce(&Cell<bool>) -> T) -> T {
+    compositor::with_states(surface, |states| {
+        states
+            .data_map
+            .insert_if_missing(ContentTypeObject::default);
+        cb(&states.data_map.get::<ContentTypeObject>().unwrap().0)
+    })
+}
+
+#[derive(Debug)]
+pub struct ContentTypeState {
+    _global: GlobalId,
+}
+
+impl ContentTypeState {
+    pub fn new(dh: &DisplayHandle) -> Self {
+        let global = dh.create_global::<State, WpContentTypeManagerV1, _>(1, ());
+        Self { _global: global }
+    }
+}
+
+impl GlobalDispatch<WpContentTypeManagerV1, ()> for State {
+    fn bind(
+        _state: &mut Self,
+        _handle: &DisplayHandle,
+        _client: &Client,
+        resource: New<WpContentTypeManagerV1>,
+        _global_data: &(),
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        data_init.init(resource, ());
+    }
+}
+
+impl Dispatch<WpContentTypeManagerV1, ()> for State {
+    fn request(
+        _state: &mut Self,
+        _client: &Client,
+        manager: &WpContentTypeManagerV1,
+        request: wp_content_type_manager_v1::Request,
+        _data: &(),
+        _dh: &DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        match request {
+            wp_content_type_manager_v1::Request::GetSurfaceContentType { id, surface } => {
+                if content_type_object(&surface, |exists| exists.replace(true)) {
+                    manager.post_error(
+                        wp_content_type_manager_v1::Error::AlreadyConstructed,
+                        "wl_surface already has a content type object",
+                    );
+                    return;
+                }
+
+                data_init.init(id, surface);
+            }
+            wp_content_type_manager_v1::Request::Destroy => {}
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl Dispatch<WpContentTypeV1, WlSurface> for State {
+    fn request(
+        _state: &mut Self,
+        _client: &Client,
+        _resource: &WpContentTypeV1,
+        request: wp_content_type_v1::Request,
+        surface: &WlSurface,
+        _dh: &DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+        // The object is inert once the surface is gone
+        if !surface.alive() {
+            return;
+        }
+
+        let content_type = match request {
+            wp_content_type_v1::Request::SetContentType { content_type } => match content_type {
+                WEnum::Value(content_type) => content_type.into(),
+                WEnum::Unknown(_) => ContentType::None,
+            },
+            wp_content_type_v1::Request::Destroy => {
+                content_type_object(surface, |exists| exists.set(false));
+                ContentType::None
+            }
+            _ => unreachable!(),
+        };
+
+        compositor::with_states(surface, |states| {
+            states
+                .cached_state
+                .pending::<ContentTypeCachedState>()
+                .content_type = content_type;
+        });
+    }
+}
@@ -1,3 +1,6 @@
+#[cfg(feature = "xwayland")]
+use std::os::unix::io::RawFd;
+
 use smithay::{
     delegate_data_device,
     input::Seat,
@@ -5,12 +8,24 @@ use smithay::{
     wayland::data_device::{ClientDndGrabHandler, DataDeviceHandler, ServerDndGrabHandler},
 };
 
+#[cfg(feature = "xwayland")]
+use crate::xwayland;
 use crate::State;
 
 impl DataDeviceHandler for State {
     fn data_device_state(&self) -> &smithay::wayland::data_device::DataDeviceState {
         &self.data_device_state
     }
+
+    #[cfg(feature = "xwayland")]
+    fn new_selection(&mut self, source: Option<WlDataSource>) {
+        xwayland::selection::wayland_selection_changed(self, source.as_ref());
+    }
+
+    #[cfg(feature = "xwayland")]
+    fn send_selection(&mut self, mime_type: String, fd: RawFd) {
+        xwayland::selection::send_selection(self, mime_type, fd);
+    }
 }
 
 impl ClientDndGrabHandler for State {
@@ -0,0 +1,187 @@
+use anodium_backend::OutputId;
+use slog_scope::warn;
+use smithay::{
+    backend::allocator::{dmabuf::Dmabuf, Buffer},
+    output::Output,
+    reexports::{
+        nix::{
+            time::{clock_gettime, ClockId},
+            unistd::{lseek, Whence},
+        },
+        wayland_protocols_wlr::export_dmabuf::v1::server::{
+            zwlr_export_dmabuf_frame_v1::{self, ZwlrExportDmabufFrameV1},
+            zwlr_export_dmabuf_manager_v1::{self, ZwlrExportDmabufManagerV1},
+        },
+        wayland_server::{
+            backend::GlobalId, Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New,
+            Resource,
+        },
+    },
+};
+
+use crate::State;
+
+#[derive(Debug)]
+pub struct ExportDmabufState {
+    _global: GlobalId,
+    /// Frames waiting for the next buffer of their output
+    pending: Vec<(Output, ZwlrExportDmabufFrameV1)>,
+}
+
+impl ExportDmabufState {
+    pub fn new(dh: &DisplayHandle) -> Self {
+        let global = dh.create_global::<State, ZwlrExportDmabufManagerV1, _>(1, ());
+        Self {
+            _global: global,
+            pending: Vec::new(),
+        }
+    }
+
+    /// Hand the freshly presented buffer of the output to the waiting frames
+    pub fn frame_presented(&mut self, output_id: &OutputId, buffer: Option<&Dmabuf>) {
+        let (frames, pending) = self
+            .pending
+            .drain(..)
+            .partition(|(output, _)| output.user_data().get::<OutputId>() == Some(output_id));
+        self.pending = pending;
+
+        for (_, frame) in frames {
+            if !frame.alive() {
+                continue;
+            }
+
+            match buffer {
+                Some(buffer) => export_buffer(&frame, buffer),
+                None => frame.cancel(zwlr_export_dmabuf_frame_v1::CancelReason::Permanent),
+            }
+        }
+    }
+
+    /// Cancel the frames waiting for an output that goes away, no buffer would ever come
+    pub fn output_removed(&mut self, output: &Output) {
+        self.pending.retain(|(pending_output, frame)| {
+            if pending_output != output {
+                return true;
+            }
+
+            if frame.alive() {
+                frame.cancel(zwlr_export_dmabuf_frame_v1::CancelReason::Permanent);
+            }
+            false
+        });
+    }
+}
+
+fn export_buffer(frame: &ZwlrExportDmabufFrameV1, buffer: &Dmabuf) {
+    let size = buffer.size();
+    let format = buffer.format();
+    let modifier: u64 = format.modifier.into();
+    // `zwp_linux_buffer_params_v1` flags
+    let buffer_flags = if buffer.y_inverted() { 1 } else { 0 };
+
+    frame.frame(
+        size.w as u32,
+        size.h as u32,
+        0,
+        0,
+        buffer_flags,
+        // The buffer goes back to the swapchain once the next frame is rendered
+        zwlr_export_dmabuf_frame_v1::Flags::Transient,
+        format.code as u32,
+        (modifier >> 32) as u32,
+        (modifier & 0xffff_ffff) as u32,
+        buffer.num_planes() as u32,
+    );
+
+    let planes = buffer
+        .handles()
+        .zip(buffer.offsets())
+        .zip(buffer.strides())
+        .enumerate();
+
+    for (index, ((fd, offset), stride)) in planes {
+        let fd_size = match lseek(fd, 0, Whence::SeekEnd) {
+            Ok(size) => size as u32,
+            Err(err) => {
+                warn!("Failed to get the size of an exported dmabuf: {}", err);
+                frame.cancel(zwlr_export_dmabuf_frame_v1::CancelReason::Temporary);
+                return;
+            }
+        };
+
+        frame.object(index as u32, fd, fd_size, offset, stride, index as u32);
+    }
+
+    match clock_gettime(ClockId::CLOCK_MONOTONIC) {
+        Ok(time) => {
+            let sec = time.tv_sec() as u64;
+            frame.ready((sec >> 32) as u32, sec as u32, time.tv_nsec() as u32);
+        }
+        Err(_) => frame.cancel(zwlr_export_dmabuf_frame_v1::CancelReason::Temporary),
+    }
+}
+
+impl GlobalDispatch<ZwlrExportDmabufManagerV1, ()> for State {
+    fn bind(
+        _state: &mut Self,
+        _handle: &DisplayHandle,
+        _client: &Client,
+        resource: New<ZwlrExportDmabufManagerV1>,
+        _global_data: &(),
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        data_init.init(resource, ());
+    }
+}
+
+impl Dispatch<ZwlrExportDmabufManagerV1, ()> for State {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        _manager: &ZwlrExportDmabufManagerV1,
+        request: zwlr_export_dmabuf_manager_v1::Request,
+        _data: &(),
+        _dh: &DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        match request {
+            // The hardware cursor can't be included, the cursor is only part of the
+            // buffer when it is drawn in software
+            zwlr_export_dmabuf_manager_v1::Request::CaptureOutput { frame, output, .. } => {
+                let frame = data_init.init(frame, ());
+
+                match Output::from_resource(&output) {
+                    // Kiosk mode doesn't let clients capture the screen
+                    Some(output) if !state.is_kiosk() => {
+                        state.export_dmabuf_state.pending.push((output, frame))
+                    }
+                    _ => frame.cancel(zwlr_export_dmabuf_frame_v1::CancelReason::Permanent),
+                }
+            }
+            zwlr_export_dmabuf_manager_v1::Request::Destroy => {}
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl Dispatch<ZwlrExportDmabufFrameV1, ()> for State {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        frame: &ZwlrExportDmabufFrameV1,
+        request: zwlr_export_dmabuf_frame_v1::Request,
+        _data: &(),
+        _dh: &DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+        match request {
+            zwlr_export_dmabuf_frame_v1::Request::Destroy => {
+                state
+                    .export_dmabuf_state
+                    .pending
+                    .retain(|(_, pending)| pending != frame);
+            }
+            _ => unreachable!(),
+        }
+    }
+}
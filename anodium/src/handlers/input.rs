@@ -1,18 +1,38 @@
+use std::time::Duration;
+
 use anodium_backend::{InputHandler, OutputId};
 use smithay::{
     backend::input::{
-        AbsolutePositionEvent, ButtonState, Event, InputEvent, KeyState, KeyboardKeyEvent,
-        PointerButtonEvent, PointerMotionEvent,
+        AbsolutePositionEvent, Axis, ButtonState, Event, InputBackend, InputEvent, KeyState,
+        KeyboardKeyEvent, PointerAxisEvent, PointerButtonEvent, PointerMotionEvent,
     },
     desktop::{self, WindowSurfaceType},
     input::{
         keyboard::{keysyms as xkb, FilterResult},
         pointer::{ButtonEvent, Focus, GrabStartData, MotionEvent, PointerHandle},
     },
-    utils::{Logical, Point, SERIAL_COUNTER},
+    output::Output,
+    reexports::{
+        calloop::{
+            timer::{TimeoutAction, Timer},
+            RegistrationToken,
+        },
+        wayland_server::protocol::wl_surface::WlSurface,
+    },
+    utils::{Logical, Point, Rectangle, SERIAL_COUNTER},
+    wayland::shell::wlr_layer::Layer,
 };
 
-use crate::{data::seat::SeatState, grabs::MoveSurfaceGrab, CalloopData, State};
+use crate::{
+    action::Action,
+    config::{FocusMode, LayerPassthrough, Modifiers, PointerInput},
+    data::{
+        seat::SeatState,
+        window::{WindowMode, WindowState},
+    },
+    grabs::{resize_grab, MoveSurfaceGrab},
+    transient, CalloopData, State,
+};
 
 impl InputHandler for CalloopData {
     fn process_input_event<I: smithay::backend::input::InputBackend>(
@@ -27,13 +47,18 @@ impl InputHandler for CalloopData {
             .find(|o| o.user_data().get::<OutputId>() == output_id)
             .cloned();
 
+        if let Some(time) = event_time(&event) {
+            SeatState::for_seat(&self.state.seat).set_last_event_time(time);
+        }
+
         match event {
             InputEvent::Keyboard { event } => {
                 let keyboard = self.state.seat.get_keyboard().unwrap();
 
                 let key_state = event.state();
 
-                keyboard.input::<(), _>(
+                // Actions run once `input` returns, the keyboard is locked inside of the filter
+                let action = keyboard.input::<Option<Action>, _>(
                     &mut self.state,
                     event.key_code(),
                     event.state(),
@@ -44,50 +69,76 @@ impl InputHandler for CalloopData {
 
                         SeatState::for_seat(&state.seat).update_pressed_keys(keysym, key_state);
 
-                        if keysym == xkb::KEY_Escape {
-                            state.loop_signal.stop();
-                        }
+                        let modifiers = Modifiers::from(modifiers);
 
-                        if keysym == xkb::KEY_t
-                            && modifiers.alt
-                            && event.state() == KeyState::Pressed
+                        // Checked before anything else, so it works whatever the client is doing
+                        if key_state == KeyState::Pressed
+                            && state.config.grab_break_key == Some((modifiers, keysym))
                         {
-                            std::process::Command::new("weston-terminal").spawn().ok();
+                            return FilterResult::Intercept(Some(Action::BreakGrabs));
+                        }
+
+                        let inhibited = state.keyboard_shortcuts_inhibit_state.is_inhibited();
 
-                            FilterResult::Intercept(())
-                        } else if keysym == xkb::KEY_g
-                            && modifiers.alt
-                            && event.state() == KeyState::Pressed
+                        // The focus cycle ends once the modifiers it was started with are released
+                        if key_state == KeyState::Released
+                            && state.focus_cycle.is_some()
+                            && !modifiers
+                                .intersects(Modifiers::CTRL | Modifiers::ALT | Modifiers::LOGO)
                         {
-                            std::process::Command::new("gtk4-demo").spawn().ok();
+                            state.finish_focus_cycle();
+                        }
+
+                        if key_state != KeyState::Pressed {
+                            state.stop_key_repeat();
+                            return FilterResult::Forward;
+                        }
 
-                            FilterResult::Intercept(())
-                        } else {
-                            FilterResult::Forward
+                        // Modifiers pressed for the next key of a sequence don't end it
+                        if state.key_sequence.is_some() && is_modifier_keysym(keysym) {
+                            return FilterResult::Forward;
+                        }
+
+                        let sequence = state.cancel_key_sequence();
+
+                        let action = sequence
+                            .as_deref()
+                            .unwrap_or(&state.config.keybinds)
+                            .iter()
+                            .find(|bind| bind.matches(modifiers, &handle))
+                            .map(|bind| bind.action.clone())
+                            // The client gets every key, except the one giving shortcuts back
+                            .filter(|action| {
+                                !inhibited || *action == Action::ReleaseShortcutsInhibitor
+                            })
+                            .filter(|action| state.is_action_allowed(action));
+
+                        match action {
+                            Some(action) => FilterResult::Intercept(Some(action)),
+                            None if sequence.is_some()
+                                && !state.config.forward_unmatched_sequence_key =>
+                            {
+                                FilterResult::Intercept(None)
+                            }
+                            None => FilterResult::Forward,
                         }
                     },
                 );
+
+                if let Some(action) = action.flatten() {
+                    self.state.run_action(&action);
+
+                    if action.is_repeatable() {
+                        self.state.start_key_repeat(action);
+                    }
+                }
             }
             InputEvent::PointerMotion { event } => {
                 let pointer = self.state.seat.get_pointer().unwrap();
                 let seat_state = SeatState::for_seat(&self.state.seat);
 
-                let mut position = seat_state.pointer_pos() + event.delta();
-
-                let max_x = self.state.space.outputs().fold(0, |acc, o| {
-                    acc + self.state.space.output_geometry(o).unwrap().size.w
-                });
-
-                let max_y = self
-                    .state
-                    .space
-                    .outputs()
-                    .next()
-                    .map(|o| self.state.space.output_geometry(o).unwrap().size.h)
-                    .unwrap_or_default();
-
-                position.x = position.x.max(0.0).min(max_x as f64 - 1.0);
-                position.y = position.y.max(0.0).min(max_y as f64 - 1.0);
+                let position =
+                    clamp_to_outputs(&self.state.space, seat_state.pointer_pos() + event.delta());
 
                 seat_state.set_pointer_pos(position);
                 self.state.pointer_motion(pointer, position, event.time());
@@ -95,9 +146,11 @@ impl InputHandler for CalloopData {
             InputEvent::PointerMotionAbsolute { event } => {
                 let pointer = self.state.seat.get_pointer().unwrap();
 
-                let output = absolute_output
-                    .unwrap_or_else(|| self.state.space.outputs().next().unwrap().clone());
-                let output_geo = self.state.space.output_geometry(&output).unwrap();
+                let output_geo = match absolute_motion_area(&self.state.space, absolute_output) {
+                    Some(output_geo) => output_geo,
+                    // There is no output to put the pointer on
+                    None => return,
+                };
                 let output_loc = output_geo.loc.to_f64();
 
                 let position = output_loc + event.position_transformed(output_geo.size);
@@ -114,22 +167,33 @@ impl InputHandler for CalloopData {
                 let button = event.button_code();
                 let button_state = event.state();
 
+                if ButtonState::Pressed == button_state {
+                    if let Some(action) = self.state.pointer_bind(PointerInput::Button(button)) {
+                        SeatState::for_seat(&self.state.seat).bind_button(button);
+                        self.state.run_action(&action);
+                        return;
+                    }
+                } else if SeatState::for_seat(&self.state.seat).release_bound_button(button) {
+                    return;
+                }
+
                 let seat_state = SeatState::for_seat(&self.state.seat);
                 let pointer_pos = seat_state.pointer_pos();
                 let is_alt_pressed = seat_state.is_key_pressed(xkb::KEY_Alt_L);
 
                 if ButtonState::Pressed == button_state {
-                    let window_under = self.state.space.window_under(pointer_pos).cloned();
+                    let window_under = self.state.window_under(pointer_pos);
 
                     if !pointer.is_grabbed() {
                         if let Some(window) = window_under {
-                            activate_and_brind_to_top(&mut self.state.space, &window);
+                            let delay = self.state.config.click_focus_delay;
 
-                            keyboard.set_focus(
-                                &mut self.state,
-                                Some(window.toplevel().wl_surface().clone()),
-                                serial,
-                            );
+                            if let (Some(delay), false) = (delay, is_alt_pressed) {
+                                self.state
+                                    .delay_click_focus(window.clone(), pointer_pos, delay);
+                            } else {
+                                self.state.click_focus(&window);
+                            }
 
                             // Check for compositor initiated move grab
                             if is_alt_pressed {
@@ -139,32 +203,23 @@ impl InputHandler for CalloopData {
                                     location: pointer_pos,
                                 };
 
-                                let initial_window_location =
-                                    self.state.space.window_location(&window).unwrap();
-
-                                let grab = MoveSurfaceGrab {
-                                    start_data,
-                                    window,
-                                    initial_window_location,
-                                };
+                                let grab =
+                                    MoveSurfaceGrab::start(&mut self.state, start_data, window);
 
                                 pointer.set_grab(&mut self.state, grab, serial, Focus::Clear);
 
                                 // Return early, we don't want to send button event to this window/surface
                                 return;
                             }
-                        } else {
-                            self.state.space.windows().for_each(|window| {
-                                window.set_activated(false);
-
-                                // TODO: Remove once smithay supports xwayland
-                                if let desktop::Kind::Xdg(_) = window.toplevel() {
-                                    window.configure();
-                                }
-                            });
+                        } else if self.state.input_surface_under(pointer_pos).is_none() {
+                            // Clicks on layer surfaces like panels keep the focused window
+                            deactivate_windows(&self.state.space);
                             keyboard.set_focus(&mut self.state, None, serial);
                         }
                     };
+                } else {
+                    // A click that did not turn into a drag
+                    self.state.commit_click_focus();
                 }
 
                 pointer.button(
@@ -178,6 +233,13 @@ impl InputHandler for CalloopData {
                 );
             }
             InputEvent::PointerAxis { event } => {
+                let action =
+                    scroll_input::<I>(&event).and_then(|input| self.state.pointer_bind(input));
+                if let Some(action) = action {
+                    self.state.run_action(&action);
+                    return;
+                }
+
                 let frame = anodium_framework::input::basic_axis_frame::<I>(&event);
 
                 let pointer = self.state.seat.get_pointer().unwrap();
@@ -188,7 +250,82 @@ impl InputHandler for CalloopData {
     }
 }
 
-fn activate_and_brind_to_top(space: &mut desktop::Space, window: &desktop::Window) {
+/// Time of the events forwarded to clients
+fn event_time<I: InputBackend>(event: &InputEvent<I>) -> Option<u32> {
+    match event {
+        InputEvent::Keyboard { event } => Some(event.time()),
+        InputEvent::PointerMotion { event } => Some(event.time()),
+        InputEvent::PointerMotionAbsolute { event } => Some(event.time()),
+        InputEvent::PointerButton { event } => Some(event.time()),
+        InputEvent::PointerAxis { event } => Some(event.time()),
+        _ => None,
+    }
+}
+
+/// Direction of a wheel click
+fn scroll_input<I: InputBackend>(event: &I::PointerAxisEvent) -> Option<PointerInput> {
+    let vertical = event.amount_discrete(Axis::Vertical).unwrap_or(0.0);
+    let horizontal = event.amount_discrete(Axis::Horizontal).unwrap_or(0.0);
+
+    if vertical < 0.0 {
+        Some(PointerInput::ScrollUp)
+    } else if vertical > 0.0 {
+        Some(PointerInput::ScrollDown)
+    } else if horizontal < 0.0 {
+        Some(PointerInput::ScrollLeft)
+    } else if horizontal > 0.0 {
+        Some(PointerInput::ScrollRight)
+    } else {
+        None
+    }
+}
+
+/// Keep the pointer on the outputs, laid out side by side
+///
+/// Without outputs the pointer stays at the origin.
+fn clamp_to_outputs(
+    space: &desktop::Space,
+    mut position: Point<f64, Logical>,
+) -> Point<f64, Logical> {
+    let max_x = space.outputs().fold(0, |acc, o| {
+        acc + space.output_geometry(o).map_or(0, |geo| geo.size.w)
+    });
+
+    let max_y = space
+        .outputs()
+        .next()
+        .and_then(|o| space.output_geometry(o))
+        .map(|geo| geo.size.h)
+        .unwrap_or_default();
+
+    position.x = position.x.min(max_x as f64 - 1.0).max(0.0);
+    position.y = position.y.min(max_y as f64 - 1.0).max(0.0);
+
+    position
+}
+
+/// Area absolute motion events are mapped to, the event's output or else the first one
+fn absolute_motion_area(
+    space: &desktop::Space,
+    output: Option<Output>,
+) -> Option<Rectangle<i32, Logical>> {
+    output
+        .or_else(|| space.outputs().next().cloned())
+        .and_then(|output| space.output_geometry(&output))
+}
+
+fn deactivate_windows(space: &desktop::Space) {
+    space.windows().for_each(|window| {
+        window.set_activated(false);
+
+        // TODO: Remove once smithay supports xwayland
+        if let desktop::Kind::Xdg(_) = window.toplevel() {
+            window.configure();
+        }
+    });
+}
+
+fn activate_window(space: &mut desktop::Space, window: &desktop::Window, raise: bool) {
     space.windows().filter(|w| *w != window).for_each(|window| {
         window.set_activated(false);
 
@@ -198,7 +335,10 @@ fn activate_and_brind_to_top(space: &mut desktop::Space, window: &desktop::Windo
         }
     });
 
-    space.raise_window(window, true);
+    if raise {
+        space.raise_window(window, true);
+        transient::raise_children(space, window);
+    }
     window.set_activated(true);
 
     // TODO: Remove once smithay supports xwayland
@@ -207,17 +347,353 @@ fn activate_and_brind_to_top(space: &mut desktop::Space, window: &desktop::Windo
     }
 }
 
+/// What the pointer hovers, as far as the keyboard focus is concerned
+#[derive(Debug)]
+enum Hovered<'a, T> {
+    Window(&'a T),
+    /// Unmanaged windows (like xwayland menus) leave the focus with their parent
+    UnmanagedWindow,
+    /// Layer surfaces like panels keep the focused window
+    LayerSurface,
+    Nothing,
+}
+
+/// Change of the keyboard focus when the pointer moves
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum HoverFocus {
+    Keep,
+    FocusHovered,
+    Clear,
+}
+
+fn hover_focus<T: PartialEq>(
+    focus_mode: FocusMode,
+    hovered: Hovered<'_, T>,
+    focused: Option<&T>,
+) -> HoverFocus {
+    match (focus_mode, hovered) {
+        (FocusMode::ClickToFocus, _) => HoverFocus::Keep,
+        (_, Hovered::Window(window)) if focused != Some(window) => HoverFocus::FocusHovered,
+        (FocusMode::Strict, Hovered::Nothing) if focused.is_some() => HoverFocus::Clear,
+        _ => HoverFocus::Keep,
+    }
+}
+
+/// Distance the pointer has to travel with a button held down for the click to count as a drag
+const DRAG_THRESHOLD: f64 = 8.0;
+
+/// Layers stacked above the windows, they get the input first
+const UPPER_LAYERS: &[Layer] = &[Layer::Overlay, Layer::Top];
+/// Layers stacked below the windows
+const LOWER_LAYERS: &[Layer] = &[Layer::Bottom, Layer::Background];
+
+/// Click focus that waits until the click turns out not to be the start of a drag
+#[derive(Debug)]
+pub struct PendingClickFocus {
+    window: desktop::Window,
+    location: Point<f64, Logical>,
+    timer: RegistrationToken,
+}
+
 impl State {
+    /// Action bound to the pointer input with the currently held modifiers
+    fn pointer_bind(&self, input: PointerInput) -> Option<Action> {
+        let modifiers = Modifiers::from(&self.seat.get_keyboard()?.modifier_state());
+
+        self.config
+            .pointer_binds
+            .iter()
+            .find(|bind| bind.modifiers == modifiers && bind.input == input)
+            .map(|bind| bind.action.clone())
+            .filter(|action| self.is_action_allowed(action))
+    }
+
+    /// Focus the clicked window, raising it if configured to
+    ///
+    /// Unmanaged windows (like xwayland menus) leave the focus with their parent.
+    fn click_focus(&mut self, window: &desktop::Window) {
+        if WindowState::for_window(window).is_unmanaged() {
+            return;
+        }
+
+        let keyboard = self.seat.get_keyboard().unwrap();
+
+        activate_window(&mut self.space, window, self.config.raise_on_click);
+        keyboard.set_focus(
+            self,
+            Some(window.toplevel().wl_surface().clone()),
+            SERIAL_COUNTER.next_serial(),
+        );
+    }
+
+    /// Raise the window and give it the keyboard focus
+    pub fn focus_window(&mut self, window: &desktop::Window) {
+        let keyboard = self.seat.get_keyboard().unwrap();
+
+        activate_window(&mut self.space, window, true);
+        keyboard.set_focus(
+            self,
+            Some(window.toplevel().wl_surface().clone()),
+            SERIAL_COUNTER.next_serial(),
+        );
+    }
+
+    /// Hold back the click focus until the button is held for `delay`, or released without dragging
+    fn delay_click_focus(
+        &mut self,
+        window: desktop::Window,
+        location: Point<f64, Logical>,
+        delay: Duration,
+    ) {
+        self.cancel_click_focus();
+
+        if WindowState::for_window(&window).is_unmanaged() {
+            return;
+        }
+
+        let timer = self
+            ._loop_handle
+            .insert_source(Timer::from_duration(delay), |_, _, data| {
+                data.state.commit_click_focus();
+                TimeoutAction::Drop
+            });
+
+        match timer {
+            Ok(timer) => {
+                self.pending_click_focus = Some(PendingClickFocus {
+                    window,
+                    location,
+                    timer,
+                });
+            }
+            Err(_) => self.click_focus(&window),
+        }
+    }
+
+    fn commit_click_focus(&mut self) {
+        if let Some(pending) = self.pending_click_focus.take() {
+            self._loop_handle.remove(pending.timer);
+
+            // The window might have been closed in the meantime
+            if self.space.window_location(&pending.window).is_some() {
+                self.click_focus(&pending.window);
+            }
+        }
+    }
+
+    /// Cancel every pointer and keyboard grab, and give inhibited shortcuts back
+    ///
+    /// Last resort for when a client started a grab or an inhibitor and stopped responding.
+    pub fn break_grabs(&mut self) {
+        let serial = SERIAL_COUNTER.next_serial();
+        let time = SeatState::for_seat(&self.seat).last_event_time();
+
+        self.stop_key_repeat();
+        self.cancel_key_sequence();
+        self.cancel_click_focus();
+        self.edge_snap = None;
+
+        if let Some(pointer) = self.seat.get_pointer() {
+            if pointer.is_grabbed() {
+                pointer.unset_grab(self, serial, time);
+            }
+        }
+
+        if let Some(keyboard) = self.seat.get_keyboard() {
+            if keyboard.is_grabbed() {
+                keyboard.unset_grab();
+            }
+        }
+
+        let windows: Vec<_> = self.space.windows().cloned().collect();
+        for window in windows {
+            resize_grab::cancel(&window);
+        }
+        self.backend.set_cursor_shape(None);
+
+        self.keyboard_shortcuts_inhibit_state.deactivate();
+    }
+
+    /// Move the pointer to the given position, as if the user moved it there
+    pub fn warp_pointer(&mut self, position: Point<f64, Logical>) {
+        let pointer = match self.seat.get_pointer() {
+            Some(pointer) => pointer,
+            None => return,
+        };
+        let time = SeatState::for_seat(&self.seat).last_event_time();
+
+        SeatState::for_seat(&self.seat).set_pointer_pos(position);
+        self.pointer_motion(pointer, position, time);
+    }
+
+    /// Send enter, leave and motion events if the surface under the resting pointer changed
+    ///
+    /// Windows move, close or get covered without the pointer moving, the hover state of
+    /// clients would be stuck until the next motion otherwise. The keyboard focus is left alone.
+    pub fn refresh_pointer_focus(&mut self) {
+        let pointer = match self.seat.get_pointer() {
+            Some(pointer) => pointer,
+            None => return,
+        };
+
+        // Grabs decide about the focus on their own
+        if pointer.is_grabbed() {
+            return;
+        }
+
+        let seat_state = SeatState::for_seat(&self.seat);
+        let position = seat_state.pointer_pos();
+        let under = self.input_surface_under(position);
+
+        if seat_state.pointer_focus() == under {
+            return;
+        }
+        seat_state.set_pointer_focus(under.clone());
+        let time = seat_state.last_event_time();
+
+        pointer.motion(
+            self,
+            under,
+            &MotionEvent {
+                location: position,
+                serial: SERIAL_COUNTER.next_serial(),
+                time,
+            },
+        );
+    }
+
+    fn cancel_click_focus(&mut self) {
+        if let Some(pending) = self.pending_click_focus.take() {
+            self._loop_handle.remove(pending.timer);
+        }
+    }
+
+    /// Find the topmost surface under the given point
+    ///
+    /// Windows stacked above the regular ones (like xwayland menus and tooltips) are checked first,
+    /// as [`desktop::Space::surface_under`] only follows the raise order.
+    fn surface_under(
+        &self,
+        position: Point<f64, Logical>,
+    ) -> Option<(desktop::Window, WlSurface, Point<i32, Logical>)> {
+        #[cfg(feature = "xwayland")]
+        if let Some(under) = self.xwayland.surface_under(&self.space, position) {
+            return Some(under);
+        }
+
+        self.space.surface_under(position, WindowSurfaceType::all())
+    }
+
+    /// Find the topmost surface of the layers above the windows under the given point
+    ///
+    /// Depending on the config, surfaces that take no keyboard input let the pointer through.
+    fn upper_layer_surface_under(
+        &self,
+        position: Point<f64, Logical>,
+    ) -> Option<(WlSurface, Point<i32, Logical>)> {
+        let skip_inert = match self.config.layer_pointer_passthrough {
+            LayerPassthrough::Never => false,
+            LayerPassthrough::OverFullscreen => self
+                .surface_under(position)
+                .map_or(false, |(window, _, _)| {
+                    WindowState::for_window(&window).mode() == WindowMode::Fullscreen
+                }),
+            LayerPassthrough::Always => true,
+        };
+
+        self.layer_surface_under(position, UPPER_LAYERS, skip_inert)
+    }
+
+    /// Find the topmost window under the given point
+    ///
+    /// Unlike [`desktop::Space::window_under`] this honors the input region of the surfaces,
+    /// so clicks on transparent areas (eg. client side shadows) fall through to the window below.
+    /// Surfaces without an input region fall back to their full buffer bounds.
+    ///
+    /// Windows covered by a layer surface above them (like a panel) are not under the point.
+    fn window_under(&self, position: Point<f64, Logical>) -> Option<desktop::Window> {
+        if self.upper_layer_surface_under(position).is_some() {
+            return None;
+        }
+
+        self.surface_under(position).map(|(window, _, _)| window)
+    }
+
+    /// Find the topmost surface under the given point, windows and layer surfaces alike
+    fn input_surface_under(
+        &self,
+        position: Point<f64, Logical>,
+    ) -> Option<(WlSurface, Point<i32, Logical>)> {
+        self.upper_layer_surface_under(position)
+            .or_else(|| {
+                self.surface_under(position)
+                    .map(|(_, surface, location)| (surface, location))
+            })
+            .or_else(|| self.layer_surface_under(position, LOWER_LAYERS, false))
+    }
+
+    /// Move the keyboard focus along with the pointer, according to the configured focus mode
+    fn focus_follows_pointer(&mut self, position: Point<f64, Logical>) {
+        let focus_mode = self.config.focus_mode;
+
+        if focus_mode == FocusMode::ClickToFocus {
+            return;
+        }
+
+        if self.seat.get_pointer().unwrap().is_grabbed() {
+            return;
+        }
+
+        let keyboard = self.seat.get_keyboard().unwrap();
+        let serial = SERIAL_COUNTER.next_serial();
+
+        let window = self.window_under(position);
+        let surface = window
+            .as_ref()
+            .map(|window| window.toplevel().wl_surface().clone());
+
+        let hovered = match (&window, &surface) {
+            (Some(window), _) if WindowState::for_window(window).is_unmanaged() => {
+                Hovered::UnmanagedWindow
+            }
+            (Some(_), Some(surface)) => Hovered::Window(surface),
+            _ if self.input_surface_under(position).is_some() => Hovered::LayerSurface,
+            _ => Hovered::Nothing,
+        };
+
+        match hover_focus(focus_mode, hovered, keyboard.current_focus().as_ref()) {
+            HoverFocus::FocusHovered => {
+                if let Some(window) = window {
+                    activate_window(&mut self.space, &window, false);
+                    keyboard.set_focus(self, surface, serial);
+                }
+            }
+            HoverFocus::Clear => {
+                deactivate_windows(&self.space);
+                keyboard.set_focus(self, None, serial);
+            }
+            HoverFocus::Keep => {}
+        }
+    }
+
     fn pointer_motion(
         &mut self,
         pointer: PointerHandle<Self>,
         position: Point<f64, Logical>,
         time: u32,
     ) {
-        let under = self
-            .space
-            .surface_under(position, WindowSurfaceType::all())
-            .map(|(_, surface, location)| (surface, location));
+        self.focus_follows_pointer(position);
+
+        let is_drag = self.pending_click_focus.as_ref().map_or(false, |pending| {
+            let delta = position - pending.location;
+            delta.x.hypot(delta.y) > DRAG_THRESHOLD
+        });
+        if is_drag {
+            self.cancel_click_focus();
+        }
+
+        let under = self.input_surface_under(position);
+        SeatState::for_seat(&self.seat).set_pointer_focus(under.clone());
 
         pointer.motion(
             self,
@@ -228,5 +704,101 @@ impl State {
                 time,
             },
         );
+
+        self.move_hardware_cursor();
+    }
+}
+
+fn is_modifier_keysym(keysym: u32) -> bool {
+    (xkb::KEY_Shift_L..=xkb::KEY_Hyper_R).contains(&keysym)
+        || (xkb::KEY_ISO_Lock..=xkb::KEY_ISO_Last_Group_Lock).contains(&keysym)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pointer_stays_at_origin_without_outputs() {
+        let space = desktop::Space::new(None);
+
+        assert_eq!(
+            clamp_to_outputs(&space, Point::from((120.0, -40.0))),
+            Point::from((0.0, 0.0))
+        );
+    }
+
+    #[test]
+    fn absolute_motion_without_outputs_is_skipped() {
+        let space = desktop::Space::new(None);
+
+        assert_eq!(absolute_motion_area(&space, None), None);
+    }
+
+    // Two windows side by side, 1 on the left and 2 on the right
+    const LEFT: u32 = 1;
+    const RIGHT: u32 = 2;
+
+    #[test]
+    fn click_to_focus_ignores_hover() {
+        for focused in [None, Some(&LEFT)] {
+            for hovered in [
+                Hovered::Window(&RIGHT),
+                Hovered::UnmanagedWindow,
+                Hovered::LayerSurface,
+                Hovered::Nothing,
+            ] {
+                assert_eq!(
+                    hover_focus(FocusMode::ClickToFocus, hovered, focused),
+                    HoverFocus::Keep
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn hovered_window_gets_focus() {
+        for focus_mode in [FocusMode::Sloppy, FocusMode::Strict] {
+            assert_eq!(
+                hover_focus(focus_mode, Hovered::Window(&RIGHT), Some(&LEFT)),
+                HoverFocus::FocusHovered
+            );
+            assert_eq!(
+                hover_focus(focus_mode, Hovered::Window(&RIGHT), None),
+                HoverFocus::FocusHovered
+            );
+            assert_eq!(
+                hover_focus(focus_mode, Hovered::Window(&LEFT), Some(&LEFT)),
+                HoverFocus::Keep
+            );
+        }
+    }
+
+    #[test]
+    fn empty_area_clears_focus_only_when_strict() {
+        assert_eq!(
+            hover_focus(FocusMode::Sloppy, Hovered::Nothing, Some(&LEFT)),
+            HoverFocus::Keep
+        );
+        assert_eq!(
+            hover_focus(FocusMode::Strict, Hovered::Nothing, Some(&LEFT)),
+            HoverFocus::Clear
+        );
+        assert_eq!(
+            hover_focus::<u32>(FocusMode::Strict, Hovered::Nothing, None),
+            HoverFocus::Keep
+        );
+    }
+
+    #[test]
+    fn layer_surfaces_and_unmanaged_windows_keep_focus() {
+        for focus_mode in [FocusMode::Sloppy, FocusMode::Strict] {
+            for hovered in [Hovered::UnmanagedWindow, Hovered::LayerSurface] {
+                assert_eq!(
+                    hover_focus(focus_mode, hovered, Some(&LEFT)),
+                    HoverFocus::Keep
+                );
+            }
+        }
     }
 }
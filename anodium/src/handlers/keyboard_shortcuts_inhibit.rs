@@ -0,0 +1,181 @@
+use std::cell::RefCell;
+
+use smithay::{
+    reexports::{
+        wayland_protocols::wp::keyboard_shortcuts_inhibit::zv1::server::{
+            zwp_keyboard_shortcuts_inhibit_manager_v1::{
+                self, ZwpKeyboardShortcutsInhibitManagerV1,
+            },
+            zwp_keyboard_shortcuts_inhibitor_v1::{self, ZwpKeyboardShortcutsInhibitorV1},
+        },
+        wayland_server::{
+            backend::GlobalId, protocol::wl_surface::WlSurface, Client, DataInit, Dispatch,
+            DisplayHandle, GlobalDispatch, New, Resource,
+        },
+    },
+    wayland::compositor,
+};
+
+use crate::State;
+
+/// Inhibitor created for the surface, there is only one seat
+#[derive(Default)]
+struct SurfaceInhibitor(RefCell<Option<ZwpKeyboardShortcutsInhibitorV1>>);
+
+fn surface_inhibitor<T>(
+    surface: &WlSurface,
+    cb: impl FnOnce(&mut Option<ZwpKeyboardShortcutsInhibitorV1>) -> T,
+) -> T {
+    compositor::with_states(surface, |states| {
+        states.data_map.insert_if_missing(SurfaceInhibitor::default);
+        let mut inhibitor = states
+            .data_map
+            .get::<SurfaceInhibitor>()
+            .unwrap()
+            .0
+            .borrow_mut();
+        cb(&mut inhibitor)
+    })
+}
+
+#[derive(Debug)]
+pub struct KeyboardShortcutsInhibitState {
+    _global: GlobalId,
+    /// Inhibitor of the focused surface, while it is in effect
+    active: Option<ZwpKeyboardShortcutsInhibitorV1>,
+}
+
+impl KeyboardShortcutsInhibitState {
+    pub fn new(dh: &DisplayHandle) -> Self {
+        let global = dh.create_global::<State, ZwpKeyboardShortcutsInhibitManagerV1, _>(1, ());
+        Self {
+            _global: global,
+            active: None,
+        }
+    }
+
+    /// Should compositor shortcuts be forwarded to the focused client
+    pub fn is_inhibited(&self) -> bool {
+        self.active
+            .as_ref()
+            .map_or(false, |inhibitor| inhibitor.alive())
+    }
+
+    /// Stop inhibiting shortcuts until the surface gets focused again
+    pub fn deactivate(&mut self) {
+        if let Some(inhibitor) = self.active.take() {
+            if inhibitor.alive() {
+                inhibitor.inactive();
+            }
+        }
+    }
+
+    /// Keyboard focus changed, inhibitors follow the focus
+    pub fn focus_changed(&mut self, focused: Option<&WlSurface>) {
+        self.deactivate();
+
+        let inhibitor = focused
+            .filter(|surface| surface.alive())
+            .and_then(|surface| surface_inhibitor(surface, |inhibitor| inhibitor.clone()));
+
+        if let Some(inhibitor) = inhibitor {
+            inhibitor.active();
+            self.active = Some(inhibitor);
+        }
+    }
+}
+
+impl State {
+    /// Keyboard focus changed, the inhibitor of the focused surface takes effect
+    ///
+    /// Kiosk mode keeps the compositor shortcuts, inhibitors never take effect there.
+    pub fn update_shortcuts_inhibitor(&mut self, focused: Option<&WlSurface>) {
+        let focused = focused.filter(|_| !self.is_kiosk());
+        self.keyboard_shortcuts_inhibit_state.focus_changed(focused);
+    }
+}
+
+impl GlobalDispatch<ZwpKeyboardShortcutsInhibitManagerV1, ()> for State {
+    fn bind(
+        _state: &mut Self,
+        _handle: &DisplayHandle,
+        _client: &Client,
+        resource: New<ZwpKeyboardShortcutsInhibitManagerV1>,
+        _global_data: &(),
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        data_init.init(resource, ());
+    }
+}
+
+impl Dispatch<ZwpKeyboardShortcutsInhibitManagerV1, ()> for State {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        manager: &ZwpKeyboardShortcutsInhibitManagerV1,
+        request: zwp_keyboard_shortcuts_inhibit_manager_v1::Request,
+        _data: &(),
+        _dh: &DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        match request {
+            zwp_keyboard_shortcuts_inhibit_manager_v1::Request::InhibitShortcuts {
+                id,
+                surface,
+                ..
+            } => {
+                let exists = surface_inhibitor(&surface, |inhibitor| {
+                    inhibitor.as_ref().map_or(false, |i| i.alive())
+                });
+
+                if exists {
+                    manager.post_error(
+                        zwp_keyboard_shortcuts_inhibit_manager_v1::Error::AlreadyInhibited,
+                        "shortcuts are already inhibited for this surface and seat",
+                    );
+                    return;
+                }
+
+                let inhibitor = data_init.init(id, surface.clone());
+                surface_inhibitor(&surface, |slot| *slot = Some(inhibitor.clone()));
+
+                let focused = state
+                    .seat
+                    .get_keyboard()
+                    .and_then(|keyboard| keyboard.current_focus());
+
+                if focused.as_ref() == Some(&surface) {
+                    state.update_shortcuts_inhibitor(Some(&surface));
+                }
+            }
+            zwp_keyboard_shortcuts_inhibit_manager_v1::Request::Destroy => {}
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl Dispatch<ZwpKeyboardShortcutsInhibitorV1, WlSurface> for State {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        inhibitor: &ZwpKeyboardShortcutsInhibitorV1,
+        request: zwp_keyboard_shortcuts_inhibitor_v1::Request,
+        surface: &WlSurface,
+        _dh: &DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+        match request {
+            zwp_keyboard_shortcuts_inhibitor_v1::Request::Destroy => {
+                if surface.alive() {
+                    surface_inhibitor(surface, |slot| *slot = None);
+                }
+
+                let inhibit_state = &mut state.keyboard_shortcuts_inhibit_state;
+                if inhibit_state.active.as_ref() == Some(inhibitor) {
+                    inhibit_state.active = None;
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+}
@@ -0,0 +1,121 @@
+use smithay::{
+    delegate_layer_shell,
+    desktop::{layer_map_for_output, LayerSurface, WindowSurfaceType},
+    output::Output,
+    reexports::wayland_server::protocol::{wl_output, wl_surface::WlSurface},
+    utils::{Logical, Point},
+    wayland::{
+        compositor,
+        shell::wlr_layer::{
+            KeyboardInteractivity, Layer, LayerSurface as WlrLayerSurface, LayerSurfaceCachedState,
+            WlrLayerShellHandler, WlrLayerShellState,
+        },
+    },
+};
+
+use crate::{timing, State};
+
+impl WlrLayerShellHandler for State {
+    fn shell_state(&mut self) -> &mut WlrLayerShellState {
+        &mut self.layer_shell_state
+    }
+
+    fn new_layer_surface(
+        &mut self,
+        surface: WlrLayerSurface,
+        output: Option<wl_output::WlOutput>,
+        _layer: Layer,
+        namespace: String,
+    ) {
+        let output = output
+            .as_ref()
+            .and_then(Output::from_resource)
+            .or_else(|| self.focused_output());
+
+        let output = match output {
+            Some(output) => output,
+            None => {
+                surface.send_close();
+                return;
+            }
+        };
+
+        // The initial configure is sent by the first arrange, once the surface commits its state
+        layer_map_for_output(&output)
+            .map_layer(&LayerSurface::new(surface, namespace))
+            .ok();
+    }
+}
+
+delegate_layer_shell!(State);
+
+/// Lay out the layer surfaces of the output the surface belongs to
+///
+/// Every anchored surface is placed at its edge. Only surfaces with a positive exclusive zone
+/// shrink the usable area, the ones with a zero zone are drawn over the windows below them.
+pub fn handle_commit(state: &State, surface: &WlSurface) {
+    let output = state.space.outputs().find(|output| {
+        layer_map_for_output(output)
+            .layer_for_surface(surface, WindowSurfaceType::TOPLEVEL)
+            .is_some()
+    });
+
+    if let Some(output) = output {
+        let start = timing::start(state.config.log_timings);
+
+        let mut map = layer_map_for_output(output);
+        map.arrange();
+
+        timing::finish(start, || {
+            format!(
+                "Arranged {} layer surfaces of output {}",
+                map.layers().count(),
+                output.name()
+            )
+        });
+    }
+}
+
+impl State {
+    /// Find the topmost layer surface of the given layers under the given point
+    ///
+    /// Layers are checked in the given order, surfaces with a zero exclusive zone take input
+    /// like any other. Points outside the input region of a surface hit the surfaces below it,
+    /// `skip_inert` lets the point through surfaces that take no keyboard input as well.
+    pub fn layer_surface_under(
+        &self,
+        position: Point<f64, Logical>,
+        layers: &[Layer],
+        skip_inert: bool,
+    ) -> Option<(WlSurface, Point<i32, Logical>)> {
+        let output = self.space.output_under(position).next()?;
+        let output_geo = self.space.output_geometry(output)?;
+        let map = layer_map_for_output(output);
+
+        let position = position - output_geo.loc.to_f64();
+
+        layers.iter().find_map(|layer| {
+            map.layers_on(*layer)
+                .rev()
+                .filter(|surface| !(skip_inert && is_inert(surface)))
+                .find_map(|surface| {
+                    let loc = map.layer_geometry(surface)?.loc;
+
+                    surface
+                        .surface_under(position - loc.to_f64(), WindowSurfaceType::all())
+                        .map(|(surface, surface_loc)| (surface, surface_loc + loc + output_geo.loc))
+                })
+        })
+    }
+}
+
+/// Does the layer surface take no keyboard input, like most bars
+fn is_inert(surface: &LayerSurface) -> bool {
+    compositor::with_states(surface.wl_surface(), |states| {
+        states
+            .cached_state
+            .current::<LayerSurfaceCachedState>()
+            .keyboard_interactivity
+            == KeyboardInteractivity::None
+    })
+}
@@ -3,7 +3,17 @@ mod input;
 mod output;
 
 mod compositor;
+mod content_type;
 mod data_device;
 mod dmabuf;
+mod export_dmabuf;
+mod keyboard_shortcuts_inhibit;
+mod layer_shell;
 mod seat;
 mod xdg;
+
+pub use content_type::ContentTypeState;
+pub use export_dmabuf::ExportDmabufState;
+pub use input::PendingClickFocus;
+pub use keyboard_shortcuts_inhibit::KeyboardShortcutsInhibitState;
+pub use output::{DIM_ELEMENT_ID, SNAP_PREVIEW_ELEMENT_ID, WALLPAPER_ELEMENT_ID};
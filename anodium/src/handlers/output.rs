@@ -1,29 +1,70 @@
+use std::time::Duration;
+
 use anodium_backend::{
     utils::cursor::PointerElement, NewOutputDescriptor, OutputHandler, OutputId,
 };
+use slog_scope::{info, warn};
 use smithay::{
-    backend::renderer::gles2::{Gles2Renderer, Gles2Texture},
+    backend::{
+        allocator::dmabuf::Dmabuf,
+        renderer::gles2::{Gles2Renderer, Gles2Texture},
+    },
     delegate_output,
-    desktop::space::SurfaceTree,
+    desktop::{
+        layer_map_for_output,
+        space::{RenderError, SurfaceTree},
+    },
     output::{Mode, Output},
+    utils::{Physical, Point},
+};
+
+use crate::{
+    closing::ClosingWindowElement,
+    config::OutputResizePlacement,
+    data::{output::OutputState, window::WindowState},
+    dim::DimElement,
+    edge_snap::SnapPreviewElement,
+    occlusion, positioning, timing,
+    wallpaper::WallpaperElement,
+    CalloopData, State,
 };
 
-use crate::{data::output::OutputState, CalloopData, State};
+// Damage is tracked per element id, so every custom element needs its own.
+// The pointer of the backend takes `0`, closing windows count up from `0x1000`.
+pub const DIM_ELEMENT_ID: usize = 1;
+pub const WALLPAPER_ELEMENT_ID: usize = 2;
+pub const SNAP_PREVIEW_ELEMENT_ID: usize = 3;
 
 smithay::custom_elements! {
     pub CustomElem<=Gles2Renderer>;
     SurfaceTree=SurfaceTree,
     PointerElement=PointerElement,
+    DimElement=DimElement,
+    SnapPreviewElement=SnapPreviewElement,
+    ClosingWindowElement=ClosingWindowElement,
+    WallpaperElement=WallpaperElement,
 }
 
 impl OutputHandler for CalloopData {
     fn output_created(&mut self, desc: NewOutputDescriptor) {
+        let virtual_outputs: Vec<_> = self
+            .state
+            .space
+            .outputs()
+            .filter(|output| OutputState::for_output(output).is_virtual())
+            .cloned()
+            .collect();
+
         let output = Output::new(desc.name.clone(), desc.physical_properties, None);
         output.set_preferred(desc.prefered_mode);
 
         output.user_data().insert_if_missing(|| desc.id);
 
-        output.create_global::<State>(&self.display.handle());
+        let overscan = self.state.config.output_overscan.get(&desc.name).copied();
+        OutputState::for_output(&output).set_overscan(overscan);
+
+        let global = output.create_global::<State>(&self.display.handle());
+        OutputState::for_output(&output).set_global(global);
 
         output.change_current_state(Some(desc.prefered_mode), Some(desc.transform), None, None);
 
@@ -67,6 +108,20 @@ impl OutputHandler for CalloopData {
 
             x += output.current_mode().unwrap().size.w;
         }
+
+        // Windows kept on a virtual output move over to the real one
+        for output in virtual_outputs {
+            info!("Replacing virtual output {}", output.name());
+            self.state.remove_output(&output);
+        }
+    }
+
+    fn use_non_desktop_output(&mut self, name: &str) -> bool {
+        self.state
+            .config
+            .non_desktop_outputs
+            .iter()
+            .any(|output| output == name)
     }
 
     fn output_mode_updated(&mut self, output_id: &OutputId, mode: Mode) {
@@ -74,15 +129,43 @@ impl OutputHandler for CalloopData {
             .state
             .space
             .outputs()
-            .find(|o| o.user_data().get::<OutputId>() == Some(output_id));
+            .find(|o| o.user_data().get::<OutputId>() == Some(output_id))
+            .cloned();
 
         if let Some(output) = output {
+            let area = positioning::usable_area(&self.state.space, &output);
+            let windows: Vec<_> = positioning::windows_on_output(&self.state.space, &output)
+                .into_iter()
+                .filter(|window| {
+                    self.state.space.outputs_for_window(window).first() == Some(&output)
+                })
+                .collect();
+
             output.change_current_state(Some(mode), None, None, None);
+            layer_map_for_output(&output).arrange();
+
+            match (self.state.config.output_resize_placement, area) {
+                (OutputResizePlacement::Proportional, Some(area)) => {
+                    positioning::rescale_windows(&mut self.state.space, &windows, &output, area);
+                }
+                _ => positioning::refit_windows(&mut self.state.space, &output),
+            }
+
+            self.state.arrange_outputs();
         }
     }
 
-    fn output_removed(&mut self, _output: &OutputId) {
-        // TODO:
+    fn output_removed(&mut self, output_id: &OutputId) {
+        let output = self
+            .state
+            .space
+            .outputs()
+            .find(|o| o.user_data().get::<OutputId>() == Some(output_id))
+            .cloned();
+
+        if let Some(output) = output {
+            self.state.remove_output(&output);
+        }
     }
 
     fn output_render(
@@ -95,6 +178,8 @@ impl OutputHandler for CalloopData {
         Option<Vec<smithay::utils::Rectangle<i32, smithay::utils::Physical>>>,
         smithay::backend::SwapBuffersError,
     > {
+        let start = timing::start(self.state.config.log_timings);
+
         let mut elems: Vec<CustomElem> = Vec::new();
 
         let location = self
@@ -120,28 +205,120 @@ impl OutputHandler for CalloopData {
             .space
             .outputs()
             .find(|o| o.user_data().get::<OutputId>() == Some(output_id))
-            .unwrap()
-            .clone();
+            .cloned();
+
+        let output = match output {
+            Some(output) => output,
+            // The output got removed while the backend still had a frame queued
+            None => return Ok(None),
+        };
+
+        let wallpaper = self.state.config.wallpaper_for(&output.name());
+        let output_size = self
+            .state
+            .space
+            .output_geometry(&output)
+            .map(|geo| geo.size);
+
+        if let (Some(wallpaper), Some(output_size)) = (wallpaper, output_size) {
+            let elapsed = self.state.start_time.elapsed();
+            let elem = WallpaperElement::new(renderer, &output, output_size, wallpaper, elapsed);
+
+            if let Some(elem) = elem {
+                elems.push(elem.into());
+            }
+        }
+
+        let clear_color = wallpaper
+            .and_then(|wallpaper| wallpaper.fill_color)
+            .unwrap_or(self.state.config.background_color);
+
+        if let Some(opacity) = self.state.config.dim_inactive_outputs {
+            if self.state.focused_output().as_ref() != Some(&output) {
+                let geometry = self.state.space.output_geometry(&output);
+                let dim = geometry
+                    .and_then(|geometry| DimElement::new(renderer, &output, geometry, opacity));
+
+                if let Some(dim) = dim {
+                    elems.push(dim.into());
+                }
+            }
+        }
+
+        if let Some(preview) = self.state.edge_snap_element(renderer, &output) {
+            elems.push(preview.into());
+        }
+
+        self.state.snapshot_windows(renderer, &output);
+        elems.extend(
+            self.state
+                .closing_elements(&output)
+                .into_iter()
+                .map(CustomElem::from),
+        );
 
         let output_state = OutputState::for_output(&output);
         // let egui = output_state.egui_frame(&output, &self.start_time);
         // elems.push(egui.into());
 
-        let render_result = self
+        let rendered = self
             .state
             .space
-            .render_output(renderer, &output, age, [0.1, 0.1, 0.1, 1.0], &elems)
-            .unwrap();
+            .render_output(renderer, &output, age, clear_color, &elems);
+
+        let render_result = match rendered {
+            Ok(result) => result,
+            // Let the backend decide between trying again and recreating the renderer
+            Err(RenderError::Rendering(err)) => return Err(err.into()),
+            Err(err) => {
+                warn!("Skipping frame of output {}: {:?}", output.name(), err);
+                return Ok(None);
+            }
+        };
 
         if render_result.is_some() {
             output_state.fps_tick();
         }
 
+        timing::finish(start, || {
+            let windows = self
+                .state
+                .space
+                .windows()
+                .filter(|window| {
+                    self.state
+                        .space
+                        .outputs_for_window(window)
+                        .contains(&output)
+                })
+                .count();
+            format!(
+                "Rendered output {} with {} windows and {} custom elements, {} damaged rects",
+                output.name(),
+                windows,
+                elems.len(),
+                render_result.as_ref().map_or(0, |damage| damage.len())
+            )
+        });
+
         Ok(render_result)
     }
 
+    fn renderer_recreated(&mut self) {
+        for output in self.state.space.outputs() {
+            OutputState::for_output(output).drop_textures();
+        }
+
+        for window in self.state.space.windows() {
+            WindowState::for_window(window).set_snapshot(None);
+        }
+        // Closing windows have nothing but their snapshot left to show
+        self.state.closing_windows.clear();
+    }
+
     fn send_frames(&mut self, output_id: &OutputId) {
-        let time = self.state.start_time.elapsed().as_millis() as u32;
+        let elapsed = self.state.start_time.elapsed();
+        let time = elapsed.as_millis() as u32;
 
         // Send frames only to relevant outputs
         for window in self.state.space.windows() {
@@ -150,13 +327,28 @@ impl OutputHandler for CalloopData {
             // Sort by refresh
             output.sort_by_key(|o| o.current_mode().map(|o| o.refresh).unwrap_or(0));
             // Get output with highest refresh
-            let best_output_id = output.last().and_then(|o| o.user_data().get::<OutputId>());
+            let best_output = match output.last() {
+                Some(output) => output,
+                // Window is off-screen, it was not composited so let it stop animating
+                None => continue,
+            };
+
+            if best_output.user_data().get::<OutputId>() != Some(output_id) {
+                continue;
+            }
 
-            if let Some(best_output_id) = best_output_id {
-                if best_output_id == output_id {
-                    window.send_frame(self.state.start_time.elapsed().as_millis() as u32);
-                }
-            } else {
+            if occlusion::is_window_occluded(&self.state.space, window) {
+                continue;
+            }
+
+            let refresh = best_output
+                .current_mode()
+                .map(|mode| mode.refresh)
+                .filter(|refresh| *refresh > 0)
+                .unwrap_or(60_000);
+            let interval = Duration::from_micros(1_000_000_000 / refresh as u64);
+
+            if WindowState::for_window(window).frame_due(elapsed, interval) {
                 window.send_frame(time);
             }
         }
@@ -175,6 +367,142 @@ impl OutputHandler for CalloopData {
         //     .space
         //     .send_frames(self.state.start_time.elapsed().as_millis() as u32);
     }
+
+    fn frame_presented(&mut self, output_id: &OutputId, buffer: Option<&Dmabuf>) {
+        self.state
+            .export_dmabuf_state
+            .frame_presented(output_id, buffer);
+    }
+
+    fn cursor_location(&mut self, output_id: &OutputId) -> Option<Point<i32, Physical>> {
+        let output = self
+            .state
+            .space
+            .outputs()
+            .find(|o| o.user_data().get::<OutputId>() == Some(output_id))?
+            .clone();
+
+        self.state.cursor_location(&output)
+    }
+}
+
+impl State {
+    /// Location of the default cursor image in the framebuffer of the output
+    ///
+    /// `None` while a client draws its own cursor surface, or the pointer is on another output.
+    pub fn cursor_location(&self, output: &Output) -> Option<Point<i32, Physical>> {
+        if !self.pointer_icon.is_default_cursor() {
+            return None;
+        }
+
+        let output_geo = self.space.output_geometry(output)?;
+
+        let location = self.seat.get_pointer()?.current_location().to_i32_round();
+
+        if !output_geo.contains(location) {
+            return None;
+        }
+
+        let scale = output.current_scale().fractional_scale();
+        let location = (location - output_geo.loc)
+            .to_f64()
+            .to_physical(scale)
+            .to_i32_round();
+
+        // The cursor plane is positioned in the untransformed framebuffer
+        let transform = output.current_transform();
+        let size = transform.transform_size(output.current_mode()?.size);
+        Some(transform.transform_point_in(location, &size))
+    }
+
+    /// Move the hardware cursor along with the pointer, without waiting for the next frame
+    pub fn move_hardware_cursor(&mut self) {
+        let outputs: Vec<_> = self.space.outputs().cloned().collect();
+
+        for output in outputs {
+            if let Some(output_id) = output.user_data().get::<OutputId>() {
+                let location = self.cursor_location(&output);
+                self.backend.move_cursor(output_id, location);
+            }
+        }
+    }
+
+    /// Take the output out of the space, the windows only shown on it move to another output
+    ///
+    /// Without another output the last one is kept as a virtual output, if configured.
+    pub fn remove_output(&mut self, output: &Output) {
+        // Even when it is kept as a virtual output, nothing gets rendered to it anymore
+        self.export_dmabuf_state.output_removed(output);
+
+        let target = self
+            .primary_output()
+            .filter(|primary| primary != output)
+            .or_else(|| self.space.outputs().find(|o| *o != output).cloned());
+
+        if target.is_none() && self.config.virtual_output_fallback {
+            info!(
+                "Keeping {} as a virtual output until an output gets connected",
+                output.name()
+            );
+            OutputState::for_output(output).set_virtual(true);
+            return;
+        }
+
+        if let Some(target) = &target {
+            for window in positioning::windows_on_output(&self.space, output) {
+                // Windows that span outputs stay where they are
+                if self.space.outputs_for_window(&window).len() == 1 {
+                    positioning::move_window_to_output(&mut self.space, &window, target);
+                }
+            }
+        }
+
+        for layer in layer_map_for_output(output).layers() {
+            layer.layer_surface().send_close();
+        }
+
+        self.space.unmap_output(output);
+        if let Some(global) = OutputState::for_output(output).take_global() {
+            self.display.remove_global::<State>(global);
+        }
+
+        self.arrange_outputs();
+    }
+
+    /// Put the outputs next to each other from left to right again, windows move along
+    fn arrange_outputs(&mut self) {
+        let mut outputs: Vec<_> = self
+            .space
+            .outputs()
+            .filter_map(|output| {
+                let geo = self.space.output_geometry(output)?;
+                // Windows that span outputs move along with the first one
+                let windows: Vec<_> = positioning::windows_on_output(&self.space, output)
+                    .into_iter()
+                    .filter(|window| self.space.outputs_for_window(window).first() == Some(output))
+                    .collect();
+                Some((output.clone(), geo, windows))
+            })
+            .collect();
+        outputs.sort_by_key(|(_, geo, _)| geo.loc.x);
+
+        let mut x = 0;
+        for (output, geo, windows) in outputs {
+            let location = Point::from((x, 0));
+            self.space.map_output(&output, location);
+            output.change_current_state(None, None, None, Some(location));
+
+            let delta = location - geo.loc;
+            if delta != Point::from((0, 0)) {
+                for window in windows {
+                    positioning::shift_window(&mut self.space, &window, delta);
+                }
+                positioning::refit_windows(&mut self.space, &output);
+            }
+
+            x += geo.size.w;
+        }
+    }
 }
 
 //
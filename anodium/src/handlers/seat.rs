@@ -18,6 +18,9 @@ impl SeatHandler for State {
     fn focus_changed(&mut self, seat: &Seat<Self>, focused: Option<&Self::KeyboardFocus>) {
         let focus = focused.and_then(|s| self.display.get_client(s.id()).ok());
         data_device::set_data_device_focus(&self.display, seat, focus);
+
+        self.update_shortcuts_inhibitor(focused);
+        self.record_focus(focused);
     }
 
     fn cursor_image(
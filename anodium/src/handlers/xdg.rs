@@ -1,18 +1,15 @@
 use smithay::{
-    backend::renderer::utils::with_renderer_surface_state,
     delegate_xdg_shell,
-    desktop::{
-        Kind, PopupKeyboardGrab, PopupKind, PopupPointerGrab, PopupUngrabStrategy, Window,
-        WindowSurfaceType,
-    },
+    desktop::{Kind, PopupKeyboardGrab, PopupKind, PopupPointerGrab, PopupUngrabStrategy, Window},
     input::{
         pointer::{Focus, GrabStartData as PointerGrabStartData},
         Seat,
     },
+    output::Output,
     reexports::{
         wayland_protocols::xdg::shell::server::xdg_toplevel,
         wayland_server::{
-            protocol::{wl_seat, wl_surface::WlSurface},
+            protocol::{wl_output, wl_seat, wl_surface::WlSurface},
             Resource,
         },
     },
@@ -23,9 +20,12 @@ use smithay::{
 };
 
 use crate::{
-    data::seat::SeatState,
+    data::{
+        surface,
+        window::{WindowMode, WindowState},
+    },
     grabs::{MoveSurfaceGrab, ResizeSurfaceGrab},
-    positioning, State,
+    positioning, transient, State,
 };
 
 impl XdgShellHandler for State {
@@ -38,19 +38,33 @@ impl XdgShellHandler for State {
 
         let window = Window::new(Kind::Xdg(surface));
         self.space.map_window(&window, (0, 0), None, false);
+        self.window_index.insert(&window);
 
         fn on_initial_commit(state: &mut State, window: Window, surface: &WlSurface) {
+            if !state.kiosk_configure(&window) {
+                return;
+            }
+
             // Send initial configure
             window.configure();
 
             fn on_commit(state: &mut State, window: Window, surface: &WlSurface) {
-                let buffer_attached =
-                    with_renderer_surface_state(surface, |data| data.wl_buffer().is_some());
-
-                if buffer_attached {
-                    // Window got mapped so we can position it
-                    let pointer_pos = SeatState::for_seat(&state.seat).pointer_pos();
-                    positioning::position_window_center(&mut state.space, window, pointer_pos);
+                if surface::is_buffer_attached(surface) {
+                    // Window got mapped so we can position it, dialogs go over their parent.
+                    // Kiosk windows were already placed fullscreen before the initial configure,
+                    // windows that asked to be maximized or fullscreen already got their geometry.
+                    let mode = WindowState::for_window(&window).mode();
+                    if !state.is_kiosk()
+                        && mode == WindowMode::Floating
+                        && !transient::center_over_parent(&mut state.space, &window)
+                    {
+                        let output = state.new_window_output();
+                        positioning::position_window_center(
+                            &mut state.space,
+                            window,
+                            output.as_ref(),
+                        );
+                    }
                 } else {
                     // Wait for nex commit
                     state
@@ -123,6 +137,55 @@ impl XdgShellHandler for State {
         }
     }
 
+    fn maximize_request(&mut self, surface: ToplevelSurface) {
+        if let Some(window) = self.window_index.get(surface.wl_surface()).cloned() {
+            let output = self
+                .space
+                .outputs_for_window(&window)
+                .first()
+                .cloned()
+                .or_else(|| self.new_window_output());
+
+            if let Some(output) = output {
+                positioning::maximize_window(&mut self.space, &window, &output);
+            }
+        }
+    }
+
+    fn unmaximize_request(&mut self, surface: ToplevelSurface) {
+        if let Some(window) = self.window_index.get(surface.wl_surface()).cloned() {
+            if WindowState::for_window(&window).mode() == WindowMode::Maximized {
+                positioning::restore_window(&mut self.space, &window);
+            }
+        }
+    }
+
+    fn fullscreen_request(
+        &mut self,
+        surface: ToplevelSurface,
+        output: Option<wl_output::WlOutput>,
+    ) {
+        if let Some(window) = self.window_index.get(surface.wl_surface()).cloned() {
+            let output = output
+                .as_ref()
+                .and_then(Output::from_resource)
+                .or_else(|| self.space.outputs_for_window(&window).first().cloned())
+                .or_else(|| self.new_window_output());
+
+            if let Some(output) = output {
+                positioning::fullscreen_window(&mut self.space, &window, &output);
+            }
+        }
+    }
+
+    fn unfullscreen_request(&mut self, surface: ToplevelSurface) {
+        if let Some(window) = self.window_index.get(surface.wl_surface()).cloned() {
+            if WindowState::for_window(&window).mode() == WindowMode::Fullscreen {
+                positioning::restore_window(&mut self.space, &window);
+            }
+        }
+    }
+
     fn move_request(&mut self, surface: ToplevelSurface, seat: wl_seat::WlSeat, serial: Serial) {
         let seat = Seat::from_resource(&seat).unwrap();
 
@@ -131,18 +194,8 @@ impl XdgShellHandler for State {
         if let Some(start_data) = check_grab(&seat, wl_surface, serial) {
             let pointer = seat.get_pointer().unwrap();
 
-            let window = self
-                .space
-                .window_for_surface(wl_surface, WindowSurfaceType::TOPLEVEL)
-                .unwrap()
-                .clone();
-            let initial_window_location = self.space.window_location(&window).unwrap();
-
-            let grab = MoveSurfaceGrab {
-                start_data,
-                window,
-                initial_window_location,
-            };
+            let window = self.window_index.get(wl_surface).unwrap().clone();
+            let grab = MoveSurfaceGrab::start(self, start_data, window);
 
             pointer.set_grab(self, grab, serial, Focus::Clear);
         }
@@ -162,11 +215,7 @@ impl XdgShellHandler for State {
         if let Some(start_data) = check_grab(&seat, wl_surface, serial) {
             let pointer = seat.get_pointer().unwrap();
 
-            let window = self
-                .space
-                .window_for_surface(wl_surface, WindowSurfaceType::TOPLEVEL)
-                .unwrap()
-                .clone();
+            let window = self.window_index.get(wl_surface).unwrap().clone();
             let initial_window_location = self.space.window_location(&window).unwrap();
             let initial_window_size = window.geometry().size;
 
@@ -177,6 +226,7 @@ impl XdgShellHandler for State {
             surface.send_configure();
 
             let grab = ResizeSurfaceGrab::start(
+                self,
                 start_data,
                 window,
                 edges.into(),
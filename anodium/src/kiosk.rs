@@ -0,0 +1,203 @@
+//! Kiosk mode, a single app kept fullscreen for the whole session
+
+use std::{
+    process::Command,
+    time::{Duration, Instant},
+};
+
+use slog_scope::{error, info, warn};
+use smithay::{
+    desktop::{Kind, Window},
+    reexports::{
+        calloop::timer::{TimeoutAction, Timer},
+        wayland_protocols::xdg::shell::server::xdg_toplevel,
+    },
+};
+
+use crate::{
+    action::Action, config::KioskWindowPolicy, data::window::WindowState, positioning, State,
+};
+
+/// How often the kiosk app is checked for having exited
+const EXIT_POLL_INTERVAL: Duration = Duration::from_millis(250);
+/// Apps exiting sooner than this after their launch are considered to fail on startup
+const QUICK_EXIT: Duration = Duration::from_secs(10);
+/// Quick exits in a row after which the app is not relaunched anymore
+const MAX_QUICK_EXITS: u32 = 5;
+/// Delay before the first relaunch after a quick exit, it doubles with each one in a row
+const RELAUNCH_DELAY: Duration = Duration::from_secs(1);
+
+/// Delay before relaunching the app after the given quick exits in a row, `None` to give up
+fn relaunch_delay(quick_exits: u32) -> Option<Duration> {
+    match quick_exits {
+        0 => Some(Duration::ZERO),
+        n if n >= MAX_QUICK_EXITS => None,
+        n => Some(RELAUNCH_DELAY * 2u32.pow(n - 1)),
+    }
+}
+
+impl State {
+    pub fn is_kiosk(&self) -> bool {
+        self.config.kiosk.is_some()
+    }
+
+    /// Can bindings run the action, kiosk mode only allows the configured ones
+    pub fn is_action_allowed(&self, action: &Action) -> bool {
+        match &self.config.kiosk {
+            Some(kiosk) => kiosk.allowed_actions.contains(action),
+            None => true,
+        }
+    }
+
+    /// Spawn the kiosk app and watch for it to exit
+    pub fn spawn_kiosk_app(&mut self) {
+        let command = match &self.config.kiosk {
+            Some(kiosk) => kiosk.command.clone(),
+            None => return,
+        };
+
+        let mut child = match Command::new("sh").arg("-c").arg(&command).spawn() {
+            Ok(child) => child,
+            Err(err) => {
+                error!("Failed to spawn kiosk app {:?}: {}", command, err);
+                self.loop_signal.stop();
+                return;
+            }
+        };
+
+        let launched = Instant::now();
+
+        let timer = Timer::from_duration(EXIT_POLL_INTERVAL);
+        let res =
+            self._loop_handle
+                .insert_source(timer, move |_, _, data| match child.try_wait() {
+                    Ok(None) => TimeoutAction::ToDuration(EXIT_POLL_INTERVAL),
+                    Ok(Some(_)) | Err(_) => {
+                        data.state.kiosk_app_exited(launched.elapsed());
+                        TimeoutAction::Drop
+                    }
+                });
+
+        if let Err(err) = res {
+            error!("Failed to watch the kiosk app: {}", err.error);
+        }
+    }
+
+    fn kiosk_app_exited(&mut self, run_time: Duration) {
+        let relaunch = self
+            .config
+            .kiosk
+            .as_ref()
+            .map_or(false, |kiosk| kiosk.relaunch);
+
+        if !relaunch {
+            info!("Kiosk app exited, closing the compositor");
+            self.loop_signal.stop();
+            return;
+        }
+
+        if run_time < QUICK_EXIT {
+            self.kiosk_quick_exits += 1;
+        } else {
+            self.kiosk_quick_exits = 0;
+        }
+
+        let delay = match relaunch_delay(self.kiosk_quick_exits) {
+            Some(delay) => delay,
+            None => {
+                error!(
+                    "Kiosk app exited right after its launch {} times in a row, closing the compositor",
+                    self.kiosk_quick_exits
+                );
+                self.loop_signal.stop();
+                return;
+            }
+        };
+
+        if delay.is_zero() {
+            info!("Kiosk app exited, relaunching it");
+        } else {
+            warn!(
+                "Kiosk app exited right after its launch, relaunching it in {:?}",
+                delay
+            );
+        }
+
+        let res = self
+            ._loop_handle
+            .insert_source(Timer::from_duration(delay), |_, _, data| {
+                data.state.spawn_kiosk_app();
+                TimeoutAction::Drop
+            });
+
+        if let Err(err) = res {
+            error!("Failed to relaunch the kiosk app: {}", err.error);
+            self.loop_signal.stop();
+        }
+    }
+
+    /// Make a new window fullscreen on the primary output, or close it if the policy blocks it
+    ///
+    /// Has to be called before the initial configure, returns `false` if the window got blocked.
+    pub fn kiosk_configure(&mut self, window: &Window) -> bool {
+        let policy = match &self.config.kiosk {
+            Some(kiosk) => kiosk.window_policy,
+            None => return true,
+        };
+
+        let xdg = if let Kind::Xdg(xdg) = window.toplevel() {
+            xdg
+        } else {
+            return true;
+        };
+
+        let other_windows = self
+            .space
+            .windows()
+            .filter(|w| *w != window && !WindowState::for_window(w).is_unmanaged())
+            .count();
+
+        if policy == KioskWindowPolicy::Block && other_windows > 0 {
+            self.space.unmap_window(window);
+            xdg.send_close();
+            return false;
+        }
+
+        let output_geo = self
+            .primary_output()
+            .and_then(|output| positioning::visible_area(&self.space, &output));
+
+        if let Some(output_geo) = output_geo {
+            xdg.with_pending_state(|state| {
+                state.states.set(xdg_toplevel::State::Fullscreen);
+                state.size = Some(output_geo.size);
+            });
+            self.space.map_window(window, output_geo.loc, None, true);
+        }
+
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn relaunch_right_away_after_a_normal_exit() {
+        assert_eq!(relaunch_delay(0), Some(Duration::ZERO));
+    }
+
+    #[test]
+    fn quick_exits_back_off() {
+        assert_eq!(relaunch_delay(1), Some(RELAUNCH_DELAY));
+        assert_eq!(relaunch_delay(2), Some(RELAUNCH_DELAY * 2));
+        assert_eq!(relaunch_delay(3), Some(RELAUNCH_DELAY * 4));
+    }
+
+    #[test]
+    fn too_many_quick_exits_give_up() {
+        assert_eq!(relaunch_delay(MAX_QUICK_EXITS), None);
+        assert_eq!(relaunch_delay(MAX_QUICK_EXITS + 1), None);
+    }
+}
@@ -0,0 +1,270 @@
+//! Layout snapshots, window geometries saved to a file and reapplied later
+//!
+//! The file starts with a version header, followed by one window per line:
+//! `app_id`, `title`, `x`, `y`, `width` and `height` separated by tabs.
+//! Tabs, newlines and backslashes in the strings are escaped with a backslash.
+
+use std::{fs, io, path::PathBuf};
+
+use slog_scope::{info, warn};
+use smithay::{
+    desktop::{Kind, Window},
+    utils::{Logical, Rectangle},
+    wayland::{compositor, shell::xdg::XdgToplevelSurfaceData},
+};
+
+use crate::{data::window::WindowState, transient, State};
+
+const HEADER: &str = "anodium-layout 1";
+
+/// Saved state of a single window
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct WindowEntry {
+    app_id: String,
+    title: String,
+    geometry: Rectangle<i32, Logical>,
+}
+
+impl WindowEntry {
+    fn to_line(&self) -> String {
+        let geo = &self.geometry;
+        format!(
+            "{}\t{}\t{}\t{}\t{}\t{}",
+            escape(&self.app_id),
+            escape(&self.title),
+            geo.loc.x,
+            geo.loc.y,
+            geo.size.w,
+            geo.size.h
+        )
+    }
+
+    fn parse(line: &str) -> Option<Self> {
+        let mut fields = line.split('\t');
+
+        let app_id = unescape(fields.next()?);
+        let title = unescape(fields.next()?);
+
+        let mut numbers = fields.map(|n| n.parse::<i32>().ok());
+        let x = numbers.next()??;
+        let y = numbers.next()??;
+        let w = numbers.next()??;
+        let h = numbers.next()??;
+
+        Some(Self {
+            app_id,
+            title,
+            geometry: Rectangle::from_loc_and_size((x, y), (w, h)),
+        })
+    }
+}
+
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+}
+
+fn unescape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some('t') => out.push('\t'),
+            Some('n') => out.push('\n'),
+            Some(c) => out.push(c),
+            None => {}
+        }
+    }
+
+    out
+}
+
+fn layout_path(name: &str) -> Option<PathBuf> {
+    let data_home = std::env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .or_else(|| {
+            std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share"))
+        })?;
+
+    // Keep the name from escaping the layouts directory
+    let name = name.replace('/', "_");
+
+    Some(data_home.join("anodium/layouts").join(name))
+}
+
+/// App id and title of the window, only xdg windows can be identified for now
+pub fn window_identity(window: &Window) -> Option<(String, String)> {
+    let xdg = if let Kind::Xdg(xdg) = window.toplevel() {
+        xdg
+    } else {
+        return None;
+    };
+
+    compositor::with_states(xdg.wl_surface(), |states| {
+        let data = states
+            .data_map
+            .get::<XdgToplevelSurfaceData>()?
+            .lock()
+            .unwrap();
+        Some((
+            data.app_id.clone().unwrap_or_default(),
+            data.title.clone().unwrap_or_default(),
+        ))
+    })
+}
+
+impl State {
+    fn layout_entries(&self) -> Vec<WindowEntry> {
+        self.space
+            .windows()
+            .filter(|window| !WindowState::for_window(window).is_unmanaged())
+            .filter_map(|window| {
+                let (app_id, title) = window_identity(window)?;
+                let loc = self.space.window_location(window)?;
+
+                Some(WindowEntry {
+                    app_id,
+                    title,
+                    geometry: Rectangle::from_loc_and_size(loc, window.geometry().size),
+                })
+            })
+            .collect()
+    }
+
+    pub fn save_layout(&self, name: &str) {
+        let path = match layout_path(name) {
+            Some(path) => path,
+            None => return,
+        };
+
+        let mut content = String::from(HEADER);
+        for entry in self.layout_entries() {
+            content.push('\n');
+            content.push_str(&entry.to_line());
+        }
+        content.push('\n');
+
+        let res = path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| fs::write(&path, content));
+
+        match res {
+            Ok(()) => info!("Layout {:?} saved to {:?}", name, path),
+            Err(err) => warn!("Failed to save layout {:?}: {}", name, err),
+        }
+    }
+
+    /// Move the running windows back to their saved geometry
+    ///
+    /// Windows are matched by app id and title first, then by app id alone.
+    /// Entries without a matching window are skipped, windows without an entry are left alone.
+    pub fn restore_layout(&mut self, name: &str) {
+        let content = match layout_path(name).map(fs::read_to_string) {
+            Some(Ok(content)) => content,
+            Some(Err(err)) if err.kind() == io::ErrorKind::NotFound => {
+                warn!("No saved layout named {:?}", name);
+                return;
+            }
+            Some(Err(err)) => {
+                warn!("Failed to read layout {:?}: {}", name, err);
+                return;
+            }
+            None => return,
+        };
+
+        let mut lines = content.lines();
+        if lines.next() != Some(HEADER) {
+            warn!("Layout {:?} has an unsupported format", name);
+            return;
+        }
+
+        let entries: Vec<WindowEntry> = lines.filter_map(WindowEntry::parse).collect();
+
+        let mut windows: Vec<(Window, String, String)> = self
+            .space
+            .windows()
+            .filter(|window| !WindowState::for_window(window).is_unmanaged())
+            .filter_map(|window| {
+                let (app_id, title) = window_identity(window)?;
+                Some((window.clone(), app_id, title))
+            })
+            .collect();
+
+        for entry in entries {
+            let id = windows
+                .iter()
+                .position(|(_, app_id, title)| *app_id == entry.app_id && *title == entry.title)
+                .or_else(|| {
+                    windows
+                        .iter()
+                        .position(|(_, app_id, _)| *app_id == entry.app_id)
+                });
+
+            let window = match id {
+                Some(id) => windows.remove(id).0,
+                None => continue,
+            };
+
+            if let Kind::Xdg(xdg) = window.toplevel() {
+                xdg.with_pending_state(|state| {
+                    state.size = Some(entry.geometry.size);
+                });
+                xdg.send_configure();
+            }
+
+            transient::map_with_children(&mut self.space, &window, entry.geometry.loc, false);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escape_round_trip() {
+        for s in [
+            "plain",
+            "a\tb",
+            "line\nbreak",
+            "back\\slash",
+            "\\t",
+            "\\\n\t",
+        ] {
+            assert_eq!(unescape(&escape(s)), s);
+        }
+
+        assert_eq!(escape("a\tb\\c\n"), "a\\tb\\\\c\\n");
+    }
+
+    #[test]
+    fn unescape_drops_trailing_backslash() {
+        assert_eq!(unescape("abc\\"), "abc");
+    }
+
+    #[test]
+    fn entry_round_trip() {
+        let entry = WindowEntry {
+            app_id: "org.example.Term".into(),
+            title: "~/src\tvim\nmain.rs".into(),
+            geometry: Rectangle::from_loc_and_size((-10, 20), (800, 600)),
+        };
+
+        assert_eq!(WindowEntry::parse(&entry.to_line()), Some(entry));
+    }
+
+    #[test]
+    fn parse_rejects_malformed_lines() {
+        assert_eq!(WindowEntry::parse(""), None);
+        assert_eq!(WindowEntry::parse("app\ttitle\t0\t0\t100"), None);
+        assert_eq!(WindowEntry::parse("app\ttitle\t0\tx\t100\t100"), None);
+    }
+}
@@ -1,6 +1,13 @@
 #![allow(irrefutable_let_patterns)]
 
-use std::{ffi::OsString, os::unix::prelude::AsRawFd, sync::Arc, time::Instant};
+use std::{
+    env,
+    ffi::OsString,
+    os::unix::{prelude::AsRawFd, process::CommandExt},
+    process::Command,
+    sync::Arc,
+    time::Instant,
+};
 
 use anodium_backend::BackendState;
 use anodium_framework::pointer_icon::PointerIcon;
@@ -13,6 +20,7 @@ use smithay::{
     reexports::{
         calloop::{
             generic::Generic, EventLoop, Interest, LoopHandle, LoopSignal, Mode, PostAction,
+            RegistrationToken,
         },
         wayland_server::{
             backend::{ClientData, ClientId, DisconnectReason},
@@ -20,18 +28,37 @@ use smithay::{
         },
     },
     wayland::{
-        compositor::CompositorState, data_device::DataDeviceState, dmabuf::DmabufState,
-        output::OutputManagerState, shell::xdg::XdgShellState, shm::ShmState,
+        compositor::CompositorState,
+        data_device::DataDeviceState,
+        dmabuf::DmabufState,
+        output::OutputManagerState,
+        shell::{wlr_layer::WlrLayerShellState, xdg::XdgShellState},
+        shm::ShmState,
         socket::ListeningSocketSource,
     },
 };
 
+mod action;
+mod backlight;
 mod cli;
+mod closing;
+mod config;
 mod data;
+mod dim;
+mod edge_snap;
+mod focus_history;
 mod grabs;
 mod handlers;
+mod kiosk;
+mod layout;
+mod occlusion;
 mod on_commit;
 mod positioning;
+mod protocols;
+mod timing;
+mod transient;
+mod wallpaper;
+mod window_index;
 #[cfg(feature = "xwayland")]
 mod xwayland;
 
@@ -42,6 +69,7 @@ pub struct CalloopData {
 
 pub struct State {
     space: desktop::Space,
+    window_index: window_index::WindowIndex,
     popups: PopupManager,
 
     display: DisplayHandle,
@@ -56,16 +84,40 @@ pub struct State {
 
     compositor_state: CompositorState,
     xdg_shell_state: XdgShellState,
+    layer_shell_state: WlrLayerShellState,
     shm_state: ShmState,
     _output_manager_state: OutputManagerState,
     seat_state: SeatState<Self>,
     data_device_state: DataDeviceState,
     dmabuf_state: DmabufState,
+    _content_type_state: handlers::ContentTypeState,
+    keyboard_shortcuts_inhibit_state: handlers::KeyboardShortcutsInhibitState,
+    export_dmabuf_state: handlers::ExportDmabufState,
 
     pointer_icon: PointerIcon,
 
+    config: config::Config,
+    key_repeat: Option<RegistrationToken>,
+    key_sequence: Option<(Vec<config::Keybind>, RegistrationToken)>,
+    quit_confirm: Option<RegistrationToken>,
+    close_confirm: Option<(desktop::Window, RegistrationToken)>,
+    restart: bool,
+    /// Times in a row the kiosk app exited right after its launch
+    kiosk_quick_exits: u32,
+    pending_click_focus: Option<handlers::PendingClickFocus>,
+    /// Windows from the most to the least recently focused
+    focus_history: Vec<desktop::Window>,
+    /// Window a running focus cycle is at
+    focus_cycle: Option<desktop::Window>,
+
+    backlights: Vec<backlight::Backlight>,
+    brightness_fade: Option<RegistrationToken>,
+
     backend: BackendState,
 
+    closing_windows: Vec<closing::ClosingWindow>,
+    edge_snap: Option<(smithay::output::Output, config::SnapRegion)>,
+
     socket_name: OsString,
 
     #[cfg(feature = "xwayland")]
@@ -123,6 +175,27 @@ fn init_wayland_listener<D>(
     socket_name
 }
 
+/// Run the compositor binary again in place of this process, with the same arguments
+///
+/// Only returns if the new process could not be started.
+fn restart_compositor(wayland_display: Option<OsString>) -> std::io::Error {
+    let exe = match env::current_exe() {
+        Ok(exe) => exe,
+        Err(err) => return err,
+    };
+
+    let mut command = Command::new(exe);
+    command.args(env::args_os().skip(1));
+
+    // Our own socket got announced to the children, the new process has to see the parent one
+    match wayland_display {
+        Some(display) => command.env("WAYLAND_DISPLAY", display),
+        None => command.env_remove("WAYLAND_DISPLAY"),
+    };
+
+    command.exec()
+}
+
 fn init_log() -> slog::Logger {
     let terminal_drain = slog_envlogger::LogBuilder::new(
         slog_term::CompactFormat::new(slog_term::TermDecorator::new().stderr().build())
@@ -149,6 +222,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let _guard = slog_scope::set_global_logger(log);
 
     let opt = cli::AnodiumCliOptions::parse();
+    let parent_wayland_display = env::var_os("WAYLAND_DISPLAY");
 
     let mut event_loop = EventLoop::<CalloopData>::try_new()?;
     let mut display = Display::new()?;
@@ -160,17 +234,30 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let dh = display.handle();
     let compositor_state = CompositorState::new::<State, _>(&dh, slog_scope::logger());
     let xdg_shell_state = XdgShellState::new::<State, _>(&dh, slog_scope::logger());
+    let layer_shell_state = WlrLayerShellState::new::<State, _>(&dh, slog_scope::logger());
     let shm_state = ShmState::new::<State, _>(&dh, vec![], slog_scope::logger());
     let output_manager_state = OutputManagerState::new_with_xdg_output::<State>(&dh);
     let mut seat_state = SeatState::<State>::new();
     let data_device_state = DataDeviceState::new::<State, _>(&dh, slog_scope::logger());
 
     let dmabuf_state = DmabufState::new();
+    let content_type_state = handlers::ContentTypeState::new(&dh);
+    let keyboard_shortcuts_inhibit_state = handlers::KeyboardShortcutsInhibitState::new(&dh);
+    let export_dmabuf_state = handlers::ExportDmabufState::new(&dh);
 
     let mut seat = seat_state.new_wl_seat(&display.handle(), "seat0", slog_scope::logger());
 
     seat.add_pointer();
-    seat.add_keyboard(Default::default(), 200, 25)?;
+    let config = config::Config {
+        log_timings: opt.log_timings,
+        ..Default::default()
+    };
+
+    seat.add_keyboard(
+        Default::default(),
+        config.key_repeat_delay as i32,
+        config.key_repeat_rate as i32,
+    )?;
 
     #[cfg(feature = "xwayland")]
     let xwayland_state =
@@ -178,6 +265,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     let state = State {
         space: desktop::Space::new(slog_scope::logger()),
+        window_index: Default::default(),
         popups: PopupManager::new(slog_scope::logger()),
         display: display.handle(),
 
@@ -191,14 +279,32 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
         compositor_state,
         xdg_shell_state,
+        layer_shell_state,
         shm_state,
         _output_manager_state: output_manager_state,
         seat_state,
         data_device_state,
         dmabuf_state,
+        _content_type_state: content_type_state,
+        keyboard_shortcuts_inhibit_state,
+        export_dmabuf_state,
 
         pointer_icon,
+        config,
+        key_repeat: None,
+        key_sequence: None,
+        quit_confirm: None,
+        close_confirm: None,
+        restart: false,
+        kiosk_quick_exits: 0,
+        pending_click_focus: None,
+        focus_history: Vec::new(),
+        focus_cycle: None,
+        backlights: backlight::Backlight::enumerate(),
+        brightness_fade: None,
         backend: BackendState::default(),
+        closing_windows: Vec::new(),
+        edge_snap: None,
 
         socket_name,
         #[cfg(feature = "xwayland")]
@@ -206,6 +312,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     };
 
     let mut data = CalloopData { state, display };
+    data.state.start_auto_brightness();
 
     anodium_backend::init(
         &mut event_loop,
@@ -215,10 +322,31 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     );
 
     event_loop.run(None, &mut data, |data| {
+        data.state.update_closing_windows();
         data.state.space.refresh(&data.display.handle());
+        for output in data.state.space.outputs() {
+            desktop::layer_map_for_output(output).cleanup();
+        }
+        data.state.window_index.cleanup();
+        data.state.refresh_pointer_focus();
+        #[cfg(feature = "xwayland")]
+        xwayland::sync_window_locations(&mut data.state);
         data.state.popups.cleanup();
         data.display.flush_clients().unwrap();
     })?;
 
+    let restart = data.state.restart;
+
+    // Release the session, DRM devices and the socket before anything gets started again
+    drop(data);
+    drop(event_loop);
+
+    if restart {
+        slog_scope::info!("Restarting the compositor");
+        let err = restart_compositor(parent_wayland_display);
+        slog_scope::error!("Failed to restart the compositor: {}", err);
+        return Err(err.into());
+    }
+
     Ok(())
 }
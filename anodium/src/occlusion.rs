@@ -0,0 +1,96 @@
+use smithay::{
+    desktop::{Space, Window},
+    utils::{Logical, Point, Rectangle},
+    wayland::compositor::{self, RectangleKind, SurfaceAttributes},
+};
+
+/// Opaque region of the window toplevel surface, in global space coordinates
+fn opaque_region(space: &Space, window: &Window) -> Vec<Rectangle<i32, Logical>> {
+    let loc = match space.window_location(window) {
+        Some(loc) => loc,
+        None => return Vec::new(),
+    };
+    let origin = loc - window.geometry().loc;
+
+    compositor::with_states(window.toplevel().wl_surface(), |states| {
+        let attrs = states.cached_state.current::<SurfaceAttributes>();
+
+        attrs
+            .opaque_region
+            .as_ref()
+            .map(|region| opaque_rects(&region.rects, origin))
+            .unwrap_or_default()
+    })
+}
+
+/// Rectangles of an opaque region moved to the given origin
+fn opaque_rects(
+    rects: &[(RectangleKind, Rectangle<i32, Logical>)],
+    origin: Point<i32, Logical>,
+) -> Vec<Rectangle<i32, Logical>> {
+    // Be conservative, regions with holes punched in them are treated as translucent
+    if rects
+        .iter()
+        .any(|(kind, _)| matches!(kind, RectangleKind::Subtract))
+    {
+        return Vec::new();
+    }
+
+    rects
+        .iter()
+        .map(|(_, rect)| {
+            let mut rect = *rect;
+            rect.loc += origin;
+            rect
+        })
+        .collect()
+}
+
+/// Check if the window is completely covered by an opaque window stacked above it
+///
+/// Translucent windows never occlude anything, as they don't set an opaque region.
+pub fn is_window_occluded(space: &Space, window: &Window) -> bool {
+    let bbox = match space.window_bbox(window) {
+        Some(bbox) => bbox,
+        None => return false,
+    };
+
+    space
+        .windows()
+        .skip_while(|w| *w != window)
+        .skip(1)
+        .flat_map(|above| opaque_region(space, above))
+        .any(|rect| rect.contains_rect(bbox))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rect(x: i32, y: i32, w: i32, h: i32) -> Rectangle<i32, Logical> {
+        Rectangle::from_loc_and_size((x, y), (w, h))
+    }
+
+    #[test]
+    fn opaque_rects_are_moved_to_origin() {
+        let rects = [
+            (RectangleKind::Add, rect(0, 0, 100, 50)),
+            (RectangleKind::Add, rect(10, 50, 80, 50)),
+        ];
+
+        assert_eq!(
+            opaque_rects(&rects, Point::from((200, 100))),
+            [rect(200, 100, 100, 50), rect(210, 150, 80, 50)]
+        );
+    }
+
+    #[test]
+    fn region_with_holes_is_translucent() {
+        let rects = [
+            (RectangleKind::Add, rect(0, 0, 100, 100)),
+            (RectangleKind::Subtract, rect(40, 40, 20, 20)),
+        ];
+
+        assert!(opaque_rects(&rects, Point::from((0, 0))).is_empty());
+    }
+}
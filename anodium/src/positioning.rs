@@ -1,12 +1,27 @@
 use smithay::{
-    desktop::{Space, Window},
-    utils::{Logical, Point},
+    desktop::{layer_map_for_output, Kind, Space, Window},
+    output::Output,
+    reexports::wayland_protocols::xdg::shell::server::xdg_toplevel,
+    utils::{Logical, Point, Rectangle, Size},
 };
 
-pub fn position_window_center(space: &mut Space, window: Window, pointer_pos: Point<f64, Logical>) {
+use crate::{
+    config::{Length, SnapRegion, WindowGeometry},
+    data::{
+        output::OutputState,
+        surface,
+        window::{WindowMode, WindowState},
+    },
+    transient,
+};
+
+#[cfg(feature = "xwayland")]
+use crate::xwayland::XWaylandState;
+
+pub fn position_window_center(space: &mut Space, window: Window, output: Option<&Output>) {
     window.refresh();
 
-    let loc = space.output_under(pointer_pos).next().map(|output| {
+    let loc = output.map(|output| {
         let output = space.output_geometry(output).unwrap();
         let window = window.geometry();
 
@@ -22,3 +37,634 @@ pub fn position_window_center(space: &mut Space, window: Window, pointer_pos: Po
         space.map_window(&window, (0, 0), None, false);
     }
 }
+
+/// Bounding box of all outputs
+pub fn outputs_geometry(space: &Space) -> Rectangle<i32, Logical> {
+    space
+        .outputs()
+        .filter_map(|output| space.output_geometry(output))
+        .reduce(|acc, geo| acc.merge(geo))
+        .unwrap_or_else(|| Rectangle::from_loc_and_size((0, 0), (0, 0)))
+}
+
+/// Area of the output that is not cut off by overscan, in global coordinates
+pub fn visible_area(space: &Space, output: &Output) -> Option<Rectangle<i32, Logical>> {
+    let output_geo = space.output_geometry(output)?;
+
+    match OutputState::for_output(output).overscan() {
+        Some(overscan) => Some(overscan.apply(output_geo)),
+        None => Some(output_geo),
+    }
+}
+
+/// Area of the output that is not covered by exclusive layer surfaces, in global coordinates
+///
+/// It is kept within the visible area, when the output has overscan compensation.
+pub fn usable_area(space: &Space, output: &Output) -> Option<Rectangle<i32, Logical>> {
+    let output_geo = space.output_geometry(output)?;
+
+    let mut area = layer_map_for_output(output).non_exclusive_zone();
+    area.loc += output_geo.loc;
+
+    let visible = visible_area(space, output)?;
+    Some(area.intersection(visible).unwrap_or(visible))
+}
+
+/// Usable area of the output the window is on
+pub fn window_usable_area(space: &Space, window: &Window) -> Option<Rectangle<i32, Logical>> {
+    let output = space.outputs_for_window(window).first()?.clone();
+    usable_area(space, &output)
+}
+
+/// Delta in pixels, percentages are taken of the usable area of the window's output
+pub fn resolve_delta(
+    space: &Space,
+    window: &Window,
+    dx: Length,
+    dy: Length,
+) -> Point<i32, Logical> {
+    let size = window_usable_area(space, window)
+        .map(|area| area.size)
+        .unwrap_or_default();
+
+    (dx.resolve(size.w), dy.resolve(size.h)).into()
+}
+
+/// Managed windows that are visible on the output, from bottom to top
+pub fn windows_on_output(space: &Space, output: &Output) -> Vec<Window> {
+    space
+        .windows()
+        .filter(|window| !WindowState::for_window(window).is_unmanaged())
+        .filter(|window| space.outputs_for_window(window).contains(output))
+        .cloned()
+        .collect()
+}
+
+/// Floating windows that are visible on the output, from bottom to top
+///
+/// Maximized, fullscreen and snapped windows keep their place when windows get arranged.
+fn floating_windows_on_output(space: &Space, output: &Output) -> Vec<Window> {
+    let mut windows = windows_on_output(space, output);
+    windows.retain(|window| WindowState::for_window(window).mode() == WindowMode::Floating);
+    windows
+}
+
+/// Arrange the windows of the output into a cascade, starting at the top-left of the usable area
+pub fn cascade_windows(space: &mut Space, output: &Output, step: i32) {
+    let area = match usable_area(space, output) {
+        Some(area) => area,
+        None => return,
+    };
+
+    let step = step.max(1);
+    // Start over from the top-left corner once the cascade reaches the middle of the area
+    let steps = (area.size.w.min(area.size.h) / 2 / step).max(1);
+
+    for (id, window) in floating_windows_on_output(space, output).iter().enumerate() {
+        let offset = step * (id as i32 % steps);
+        let loc = area.loc + Point::from((offset, offset));
+
+        space.map_window(window, loc, None, false);
+    }
+}
+
+/// Arrange the windows of the output into a non overlapping grid
+///
+/// This is a one-shot arrangement, windows stay floating afterwards.
+pub fn tile_windows_grid(
+    space: &mut Space,
+    #[cfg(feature = "xwayland")] xwayland: &XWaylandState,
+    output: &Output,
+) {
+    let area = match usable_area(space, output) {
+        Some(area) => area,
+        None => return,
+    };
+
+    let windows = floating_windows_on_output(space, output);
+    if windows.is_empty() {
+        return;
+    }
+
+    let count = windows.len() as i32;
+    let cols = (count as f64).sqrt().ceil() as i32;
+    let rows = (count + cols - 1) / cols;
+
+    let cell_w = area.size.w / cols;
+    let cell_h = area.size.h / rows;
+
+    for (id, window) in windows.iter().enumerate() {
+        let col = id as i32 % cols;
+        let row = id as i32 / cols;
+
+        match window.toplevel() {
+            Kind::Xdg(xdg) => {
+                xdg.with_pending_state(|state| {
+                    state.size = Some((cell_w, cell_h).into());
+                });
+                xdg.send_configure();
+            }
+            #[cfg(feature = "xwayland")]
+            Kind::X11(_) => xwayland.configure_size(window, (cell_w, cell_h).into()),
+        }
+
+        let loc = area.loc + Point::from((col * cell_w, row * cell_h));
+        space.map_window(window, loc, None, false);
+    }
+}
+
+/// Move the window by the given delta, keeping it within the outputs
+pub fn move_window_by(space: &mut Space, window: &Window, delta: Point<i32, Logical>) {
+    let loc = match space.window_location(window) {
+        Some(loc) => loc,
+        None => return,
+    };
+
+    let bounds = outputs_geometry(space);
+    let size = window.geometry().size;
+
+    let max_x = (bounds.loc.x + bounds.size.w - size.w).max(bounds.loc.x);
+    let max_y = (bounds.loc.y + bounds.size.h - size.h).max(bounds.loc.y);
+
+    let loc = loc + delta;
+    let loc = (
+        loc.x.clamp(bounds.loc.x, max_x),
+        loc.y.clamp(bounds.loc.y, max_y),
+    );
+
+    transient::map_with_children(space, window, loc.into(), false);
+}
+
+/// Resize the window by the given delta, respecting the size limits of the client
+/// and keeping the bottom-right corner within the outputs
+pub fn resize_window_by(space: &Space, window: &Window, delta: Size<i32, Logical>) {
+    let xdg = if let Kind::Xdg(xdg) = window.toplevel() {
+        xdg
+    } else {
+        return;
+    };
+
+    let loc = match space.window_location(window) {
+        Some(loc) => loc,
+        None => return,
+    };
+
+    let bounds = outputs_geometry(space);
+    let (min_size, mut max_size) = surface::size_constraints(xdg.wl_surface());
+
+    max_size.w = max_size
+        .w
+        .min(bounds.loc.x + bounds.size.w - loc.x)
+        .max(min_size.w);
+    max_size.h = max_size
+        .h
+        .min(bounds.loc.y + bounds.size.h - loc.y)
+        .max(min_size.h);
+
+    let size = window.geometry().size;
+    let size = Size::from((
+        (size.w + delta.w).clamp(min_size.w, max_size.w),
+        (size.h + delta.h).clamp(min_size.h, max_size.h),
+    ));
+
+    xdg.with_pending_state(|state| {
+        state.size = Some(size);
+    });
+    xdg.send_configure();
+}
+
+/// Center the window in the usable area of its output
+///
+/// Windows larger than the usable area are aligned to its top-left corner,
+/// instead of being pushed off-screen.
+pub fn center_window(space: &mut Space, window: &Window) {
+    let area = match window_usable_area(space, window) {
+        Some(area) => area,
+        None => return,
+    };
+
+    let size = window.geometry().size;
+
+    let x = area.loc.x + ((area.size.w - size.w) / 2).max(0);
+    let y = area.loc.y + ((area.size.h - size.h) / 2).max(0);
+
+    space.map_window(window, (x, y), None, false);
+}
+
+/// Move and resize the window within the usable area of its output
+///
+/// The geometry is clamped to the usable area and to the size limits of the client.
+pub fn set_window_geometry(space: &mut Space, window: &Window, geometry: &WindowGeometry) {
+    let (area, loc) = match (
+        window_usable_area(space, window),
+        space.window_location(window),
+    ) {
+        (Some(area), Some(loc)) => (area, loc),
+        _ => return,
+    };
+
+    let current = window.geometry().size;
+    let mut size = Size::from((
+        geometry.w.map_or(current.w, |w| w.resolve(area.size.w)),
+        geometry.h.map_or(current.h, |h| h.resolve(area.size.h)),
+    ));
+
+    if let Kind::Xdg(xdg) = window.toplevel() {
+        let (min_size, max_size) = surface::size_constraints(xdg.wl_surface());
+
+        size.w = size.w.min(area.size.w).min(max_size.w).max(min_size.w);
+        size.h = size.h.min(area.size.h).min(max_size.h).max(min_size.h);
+
+        if size != current {
+            xdg.with_pending_state(|state| {
+                state.size = Some(size);
+            });
+            xdg.send_configure();
+        }
+    } else {
+        size = current;
+    }
+
+    let x = geometry
+        .x
+        .map_or(loc.x - area.loc.x, |x| x.resolve(area.size.w))
+        .clamp(0, (area.size.w - size.w).max(0));
+    let y = geometry
+        .y
+        .map_or(loc.y - area.loc.y, |y| y.resolve(area.size.h))
+        .clamp(0, (area.size.h - size.h).max(0));
+
+    transient::map_with_children(space, window, area.loc + Point::from((x, y)), false);
+}
+
+/// Location that keeps the center of the rectangle at the same relative position,
+/// moved from one area to another
+///
+/// The rectangle is clamped to the target area, larger ones are aligned to its top-left corner.
+fn translate_to_area(
+    rect: Rectangle<i32, Logical>,
+    from: Rectangle<i32, Logical>,
+    to: Rectangle<i32, Logical>,
+) -> Point<i32, Logical> {
+    let scale = |offset: i32, from: i32, to: i32| {
+        (offset as f64 / from.max(1) as f64 * to as f64).round() as i32
+    };
+
+    let center_x = rect.loc.x - from.loc.x + rect.size.w / 2;
+    let center_y = rect.loc.y - from.loc.y + rect.size.h / 2;
+    let x = scale(center_x, from.size.w, to.size.w) - rect.size.w / 2;
+    let y = scale(center_y, from.size.h, to.size.h) - rect.size.h / 2;
+    let x = x.clamp(0, (to.size.w - rect.size.w).max(0));
+    let y = y.clamp(0, (to.size.h - rect.size.h).max(0));
+
+    to.loc + Point::from((x, y))
+}
+
+/// Move the window to the usable area of another output, keeping its relative position
+///
+/// Maximized and fullscreen windows are fitted to the new output,
+/// and their restore geometry is moved along.
+pub fn move_window_to_output(space: &mut Space, window: &Window, output: &Output) {
+    let (from, to, loc) = match (
+        window_usable_area(space, window),
+        usable_area(space, output),
+        space.window_location(window),
+    ) {
+        (Some(from), Some(to), Some(loc)) => (from, to, loc),
+        _ => return,
+    };
+
+    let state = WindowState::for_window(window);
+    if let Some(restore) = state.restore_geometry() {
+        let loc = translate_to_area(restore, from, to);
+        state.set_restore_geometry(Some(Rectangle::from_loc_and_size(loc, restore.size)));
+    }
+
+    match state.mode() {
+        WindowMode::Maximized => maximize_window(space, window, output),
+        WindowMode::Fullscreen => fullscreen_window(space, window, output),
+        WindowMode::Snapped { region, step } => snap_window_to(space, window, output, region, step),
+        WindowMode::Floating => {
+            let rect = Rectangle::from_loc_and_size(loc, window.geometry().size);
+            let loc = translate_to_area(rect, from, to);
+            transient::map_with_children(space, window, loc, false);
+        }
+    }
+}
+
+/// Move the window along with its output, its floating geometry moves as well
+pub fn shift_window(space: &mut Space, window: &Window, delta: Point<i32, Logical>) {
+    let state = WindowState::for_window(window);
+    if let Some(mut restore) = state.restore_geometry() {
+        restore.loc += delta;
+        state.set_restore_geometry(Some(restore));
+    }
+
+    if let Some(loc) = space.window_location(window) {
+        transient::map_with_children(space, window, loc + delta, false);
+    }
+}
+
+/// Keep the windows at the same relative position within the usable area of the output,
+/// after the area changed from `from`
+///
+/// Maximized, fullscreen and snapped windows are fitted to the new area,
+/// their floating geometry is moved along.
+pub fn rescale_windows(
+    space: &mut Space,
+    windows: &[Window],
+    output: &Output,
+    from: Rectangle<i32, Logical>,
+) {
+    let to = match usable_area(space, output) {
+        Some(to) => to,
+        None => return,
+    };
+
+    for window in windows {
+        let state = WindowState::for_window(window);
+        if let Some(restore) = state.restore_geometry() {
+            let loc = translate_to_area(restore, from, to);
+            state.set_restore_geometry(Some(Rectangle::from_loc_and_size(loc, restore.size)));
+        }
+
+        if state.mode() == WindowMode::Floating {
+            if let Some(loc) = space.window_location(window) {
+                let rect = Rectangle::from_loc_and_size(loc, window.geometry().size);
+                let loc = translate_to_area(rect, from, to);
+                transient::map_with_children(space, window, loc, false);
+            }
+        }
+    }
+
+    refit_windows(space, output);
+}
+
+/// Make the window fill the usable area of the output
+///
+/// Layer surfaces with a zero exclusive zone don't shrink the usable area, so the window
+/// extends under them. Panels on the top layer stay drawn above it and keep their input.
+pub fn maximize_window(space: &mut Space, window: &Window, output: &Output) {
+    if let Some(area) = usable_area(space, output) {
+        set_window_mode(space, window, WindowMode::Maximized, area);
+    }
+}
+
+/// Make the window cover the visible area of the output
+pub fn fullscreen_window(space: &mut Space, window: &Window, output: &Output) {
+    if let Some(geometry) = visible_area(space, output) {
+        set_window_mode(space, window, WindowMode::Fullscreen, geometry);
+    }
+}
+
+/// Sizes snapped windows cycle through, as fractions of the usable area
+const SNAP_FRACTIONS: [(i32, i32); 3] = [(1, 2), (1, 3), (2, 3)];
+
+/// Rectangle of the region within the area, `step` picks the size from [`SNAP_FRACTIONS`]
+fn snap_rect(
+    region: SnapRegion,
+    area: Rectangle<i32, Logical>,
+    step: usize,
+) -> Rectangle<i32, Logical> {
+    let first = match region {
+        SnapRegion::LeftThird | SnapRegion::CenterThird | SnapRegion::RightThird => 1,
+        _ => 0,
+    };
+    let (num, den) = SNAP_FRACTIONS[(first + step) % SNAP_FRACTIONS.len()];
+
+    let w = area.size.w * num / den;
+    let h = area.size.h * num / den;
+    let half_h = area.size.h / 2;
+
+    let left = 0;
+    let right = area.size.w - w;
+    let bottom = area.size.h - h;
+
+    let (x, y, w, h) = match region {
+        SnapRegion::LeftHalf | SnapRegion::LeftThird => (left, 0, w, area.size.h),
+        SnapRegion::RightHalf | SnapRegion::RightThird => (right, 0, w, area.size.h),
+        SnapRegion::CenterThird => (right / 2, 0, w, area.size.h),
+        SnapRegion::TopHalf => (0, 0, area.size.w, h),
+        SnapRegion::BottomHalf => (0, bottom, area.size.w, h),
+        SnapRegion::TopLeftQuarter => (left, 0, w, half_h),
+        SnapRegion::TopRightQuarter => (right, 0, w, half_h),
+        SnapRegion::BottomLeftQuarter => (left, area.size.h - half_h, w, half_h),
+        SnapRegion::BottomRightQuarter => (right, area.size.h - half_h, w, half_h),
+        SnapRegion::Maximized => (0, 0, area.size.w, area.size.h),
+    };
+
+    Rectangle::from_loc_and_size(area.loc + Point::from((x, y)), (w, h))
+}
+
+/// Snap the window to a region of the usable area of its output
+///
+/// Snapping to the region the window is already in moves on to the next size.
+pub fn snap_window(space: &mut Space, window: &Window, region: SnapRegion) {
+    let output = match space.outputs_for_window(window).first() {
+        Some(output) => output.clone(),
+        None => return,
+    };
+
+    if region == SnapRegion::Maximized {
+        maximize_window(space, window, &output);
+        return;
+    }
+
+    let step = match WindowState::for_window(window).mode() {
+        WindowMode::Snapped {
+            region: current,
+            step,
+        } if current == region => step + 1,
+        _ => 0,
+    };
+
+    snap_window_to(space, window, &output, region, step);
+}
+
+/// Snap the window to the first size of a region of the given output
+pub fn snap_window_on(space: &mut Space, window: &Window, output: &Output, region: SnapRegion) {
+    if region == SnapRegion::Maximized {
+        maximize_window(space, window, output);
+    } else {
+        snap_window_to(space, window, output, region, 0);
+    }
+}
+
+/// Geometry the first size of a region of the output covers
+pub fn snap_region_rect(
+    space: &Space,
+    output: &Output,
+    region: SnapRegion,
+) -> Option<Rectangle<i32, Logical>> {
+    usable_area(space, output).map(|area| snap_rect(region, area, 0))
+}
+
+fn snap_window_to(
+    space: &mut Space,
+    window: &Window,
+    output: &Output,
+    region: SnapRegion,
+    step: usize,
+) {
+    if let Some(area) = usable_area(space, output) {
+        let rect = snap_rect(region, area, step);
+        set_window_mode(space, window, WindowMode::Snapped { region, step }, rect);
+    }
+}
+
+/// Fit the maximized and fullscreen windows of the output to its current area again
+pub fn refit_windows(space: &mut Space, output: &Output) {
+    for window in windows_on_output(space, output) {
+        match WindowState::for_window(&window).mode() {
+            WindowMode::Maximized => maximize_window(space, &window, output),
+            WindowMode::Fullscreen => fullscreen_window(space, &window, output),
+            WindowMode::Snapped { region, step } => {
+                snap_window_to(space, &window, output, region, step)
+            }
+            WindowMode::Floating => {}
+        }
+    }
+}
+
+/// Put the window into the given geometry, remembering its floating geometry
+fn set_window_mode(
+    space: &mut Space,
+    window: &Window,
+    mode: WindowMode,
+    geometry: Rectangle<i32, Logical>,
+) {
+    let xdg = if let Kind::Xdg(xdg) = window.toplevel() {
+        xdg
+    } else {
+        return;
+    };
+
+    let state = WindowState::for_window(window);
+    if state.restore_geometry().is_none() {
+        // Windows that ask for it before being mapped have no floating geometry yet
+        let size = window.geometry().size;
+        let restore = space
+            .window_location(window)
+            .filter(|_| size.w > 0 && size.h > 0)
+            .map(|loc| Rectangle::from_loc_and_size(loc, size));
+        state.set_restore_geometry(restore);
+    }
+    state.set_mode(mode);
+
+    xdg.with_pending_state(|state| {
+        state.states.unset(xdg_toplevel::State::Maximized);
+        state.states.unset(xdg_toplevel::State::Fullscreen);
+        match mode {
+            WindowMode::Maximized => state.states.set(xdg_toplevel::State::Maximized),
+            WindowMode::Fullscreen => state.states.set(xdg_toplevel::State::Fullscreen),
+            WindowMode::Snapped { .. } | WindowMode::Floating => {}
+        }
+        state.size = Some(geometry.size);
+    });
+    xdg.send_configure();
+
+    transient::map_with_children(space, window, geometry.loc, false);
+}
+
+/// Bring the window back to its floating geometry for a move, keeping the grabbed spot
+/// under the pointer
+///
+/// The horizontal grab position stays proportional to the width, the vertical one is kept
+/// since it is usually on the titlebar.
+pub fn restore_window_for_move(space: &mut Space, window: &Window, pointer: Point<f64, Logical>) {
+    let restore = WindowState::for_window(window).restore_geometry();
+    let (loc, restore) = match (space.window_location(window), restore) {
+        (Some(loc), Some(restore)) => (loc, restore),
+        _ => return restore_window(space, window),
+    };
+
+    let size = window.geometry().size;
+    let offset = pointer - loc.to_f64();
+    let ratio = offset.x / size.w.max(1) as f64;
+
+    let loc = Point::<f64, Logical>::from((
+        pointer.x - ratio * restore.size.w as f64,
+        pointer.y - offset.y.min(restore.size.h as f64),
+    ));
+
+    restore_window(space, window);
+    transient::map_with_children(space, window, loc.to_i32_round(), false);
+}
+
+/// Bring a maximized, fullscreen or snapped window back to its floating geometry
+pub fn restore_window(space: &mut Space, window: &Window) {
+    let xdg = if let Kind::Xdg(xdg) = window.toplevel() {
+        xdg
+    } else {
+        return;
+    };
+
+    let state = WindowState::for_window(window);
+    let restore = state.restore_geometry();
+    state.set_restore_geometry(None);
+    state.set_mode(WindowMode::Floating);
+
+    xdg.with_pending_state(|state| {
+        state.states.unset(xdg_toplevel::State::Maximized);
+        state.states.unset(xdg_toplevel::State::Fullscreen);
+        state.size = restore.map(|geometry| geometry.size);
+    });
+    xdg.send_configure();
+
+    if let Some(restore) = restore {
+        transient::map_with_children(space, window, restore.loc, false);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rect(x: i32, y: i32, w: i32, h: i32) -> Rectangle<i32, Logical> {
+        Rectangle::from_loc_and_size((x, y), (w, h))
+    }
+
+    #[test]
+    fn translate_between_equal_areas_keeps_offset() {
+        let loc = translate_to_area(
+            rect(100, 200, 400, 300),
+            rect(0, 0, 1920, 1080),
+            rect(1920, 0, 1920, 1080),
+        );
+
+        assert_eq!(loc, Point::from((2020, 200)));
+    }
+
+    #[test]
+    fn translate_scales_center() {
+        let loc = translate_to_area(
+            rect(400, 400, 200, 200),
+            rect(0, 0, 1000, 1000),
+            rect(0, 0, 2000, 500),
+        );
+
+        assert_eq!(loc, Point::from((900, 150)));
+    }
+
+    #[test]
+    fn translate_clamps_to_target_area() {
+        let loc = translate_to_area(
+            rect(900, 0, 200, 100),
+            rect(0, 0, 1000, 1000),
+            rect(1000, 0, 500, 500),
+        );
+
+        assert_eq!(loc, Point::from((1300, 0)));
+    }
+
+    #[test]
+    fn translate_aligns_larger_rect_to_top_left() {
+        let loc = translate_to_area(
+            rect(0, 0, 800, 800),
+            rect(0, 0, 1000, 1000),
+            rect(100, 100, 500, 500),
+        );
+
+        assert_eq!(loc, Point::from((100, 100)));
+    }
+}
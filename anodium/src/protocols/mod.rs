@@ -0,0 +1,15 @@
+//! Bindings for protocols that are not provided by `wayland-protocols` yet
+
+pub mod content_type_v1 {
+    use smithay::reexports::wayland_server::{self, protocol::*};
+
+    pub mod __interfaces {
+        use smithay::reexports::wayland_server::{
+            backend as wayland_backend, protocol::__interfaces::*,
+        };
+        wayland_scanner::generate_interfaces!("protocols/content-type-v1.xml");
+    }
+    use self::__interfaces::*;
+
+    wayland_scanner::generate_server_code!("protocols/content-type-v1.xml");
+}
@@ -0,0 +1,19 @@
+//! Timed trace events around the hot paths, so performance issues can come with numbers
+//!
+//! They are only logged with `--log-timings`, otherwise not even the clock is read.
+
+use std::time::Instant;
+
+use slog_scope::trace;
+
+/// Start timing a hot path, `None` when timings are not logged
+pub fn start(enabled: bool) -> Option<Instant> {
+    enabled.then(Instant::now)
+}
+
+/// Log how long the hot path took, the description is only built when it gets logged
+pub fn finish(start: Option<Instant>, describe: impl FnOnce() -> String) {
+    if let Some(start) = start {
+        trace!("{} in {:?}", describe(), start.elapsed());
+    }
+}
@@ -0,0 +1,100 @@
+//! Transient windows, dialogs that the client attached to a parent with `xdg_toplevel.set_parent`
+//!
+//! They are kept stacked directly above their parent and move along with it.
+
+use smithay::{
+    desktop::{Kind, Space, Window},
+    reexports::wayland_server::protocol::wl_surface::WlSurface,
+    utils::{Logical, Point},
+};
+
+use crate::positioning;
+
+/// Surface of the window's parent
+fn parent_surface(window: &Window) -> Option<WlSurface> {
+    if let Kind::Xdg(xdg) = window.toplevel() {
+        xdg.parent()
+    } else {
+        None
+    }
+}
+
+/// Parent window of the window, if it is transient for one
+pub fn parent_of(space: &Space, window: &Window) -> Option<Window> {
+    let parent = parent_surface(window)?;
+    space
+        .windows()
+        .find(|w| w.toplevel().wl_surface() == &parent)
+        .cloned()
+}
+
+/// Transient children of the window, from bottom to top
+pub fn children_of(space: &Space, window: &Window) -> Vec<Window> {
+    let surface = window.toplevel().wl_surface();
+    space
+        .windows()
+        .filter(|w| parent_surface(w).as_ref() == Some(surface))
+        .cloned()
+        .collect()
+}
+
+/// Raise the transient children, and theirs, above the window
+pub fn raise_children(space: &mut Space, window: &Window) {
+    for child in children_of(space, window) {
+        space.raise_window(&child, false);
+        raise_children(space, &child);
+    }
+}
+
+/// Map the window at the given location, carrying its transient children along
+pub fn map_with_children(
+    space: &mut Space,
+    window: &Window,
+    location: Point<i32, Logical>,
+    activate: bool,
+) {
+    let delta = match space.window_location(window) {
+        Some(old) => location - old,
+        None => return,
+    };
+
+    space.map_window(window, location, None, activate);
+
+    for child in children_of(space, window) {
+        if let Some(child_location) = space.window_location(&child) {
+            map_with_children(space, &child, child_location + delta, false);
+        }
+    }
+}
+
+/// Center the window over its parent, within the usable area of the parent's output
+///
+/// Returns `false` if the window is not transient for a mapped window.
+pub fn center_over_parent(space: &mut Space, window: &Window) -> bool {
+    let parent = match parent_of(space, window) {
+        Some(parent) => parent,
+        None => return false,
+    };
+
+    let parent_loc = match space.window_location(&parent) {
+        Some(loc) => loc,
+        None => return false,
+    };
+
+    window.refresh();
+
+    let parent_size = parent.geometry().size;
+    let size = window.geometry().size;
+    let mut x = parent_loc.x + (parent_size.w - size.w) / 2;
+    let mut y = parent_loc.y + (parent_size.h - size.h) / 2;
+
+    // Dialogs larger than the area are aligned to its top-left corner
+    if let Some(area) = positioning::window_usable_area(space, &parent) {
+        x = x.clamp(area.loc.x, area.loc.x + (area.size.w - size.w).max(0));
+        y = y.clamp(area.loc.y, area.loc.y + (area.size.h - size.h).max(0));
+    }
+
+    space.map_window(window, (x, y), None, false);
+
+    true
+}
@@ -0,0 +1,283 @@
+//! Wallpaper images drawn below everything else on the outputs
+//!
+//! A wallpaper is either a single image, or a directory of images played as an animation.
+
+use std::{
+    cell::RefCell,
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime},
+};
+
+use slog_scope::warn;
+use smithay::{
+    backend::renderer::{
+        gles2::{Gles2Error, Gles2Frame, Gles2Renderer, Gles2Texture},
+        Frame, ImportMem, Texture,
+    },
+    desktop::space::{RenderElement, RenderZindex, SpaceOutputTuple},
+    output::Output,
+    utils::{Buffer, Logical, Physical, Point, Rectangle, Scale, Size, Transform},
+};
+
+use crate::{
+    config::{WallpaperConfig, WallpaperMode},
+    data::output::OutputState,
+    handlers::WALLPAPER_ELEMENT_ID,
+};
+
+/// How often the wallpaper file is checked for changes
+const RELOAD_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Wallpaper textures of an output, stored in [`OutputState`]
+#[derive(Debug)]
+pub struct WallpaperTexture {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    checked: Instant,
+    /// Animation frames, a static wallpaper has only one
+    frames: Vec<Gles2Texture>,
+    /// Frame returned by the last call, the wallpaper only gets damaged when it changes
+    last_frame: Option<usize>,
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// Load the image, or all PNG images of the directory sorted by name
+fn load_frames(renderer: &mut Gles2Renderer, path: &Path) -> Vec<Gles2Texture> {
+    if !path.is_dir() {
+        return load(renderer, path).into_iter().collect();
+    }
+
+    let mut files: Vec<PathBuf> = match std::fs::read_dir(path) {
+        Ok(entries) => entries
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().map_or(false, |ext| ext == "png"))
+            .collect(),
+        Err(err) => {
+            warn!("Failed to read wallpaper directory {:?}: {}", path, err);
+            return Vec::new();
+        }
+    };
+    files.sort();
+
+    files
+        .iter()
+        .filter_map(|file| load(renderer, file))
+        .collect()
+}
+
+fn load(renderer: &mut Gles2Renderer, path: &Path) -> Option<Gles2Texture> {
+    let image = image::open(path)
+        .map_err(|err| warn!("Failed to load wallpaper {:?}: {}", path, err))
+        .ok()?
+        .into_rgba8();
+
+    let size = (image.width() as i32, image.height() as i32);
+    renderer
+        .import_memory(image.as_raw(), size.into(), false)
+        .map_err(|err| warn!("Failed to upload wallpaper {:?}: {}", path, err))
+        .ok()
+}
+
+impl WallpaperTexture {
+    /// Current frame of the wallpaper at `path`, (re)loaded when the path or the file changes
+    ///
+    /// Animations advance by one frame every `frame_duration` of `elapsed` time.
+    /// The flag is set when the texture changed since the last call.
+    pub fn get(
+        cache: &RefCell<Option<Self>>,
+        renderer: &mut Gles2Renderer,
+        path: &Path,
+        frame_duration: Duration,
+        elapsed: Duration,
+    ) -> Option<(Gles2Texture, bool)> {
+        let mut cache = cache.borrow_mut();
+
+        let stale = match &mut *cache {
+            Some(cached) if cached.path != path => true,
+            Some(cached) if cached.checked.elapsed() >= RELOAD_CHECK_INTERVAL => {
+                cached.checked = Instant::now();
+                modified(path) != cached.modified
+            }
+            Some(_) => false,
+            None => true,
+        };
+
+        if stale {
+            *cache = Some(Self {
+                path: path.to_owned(),
+                modified: modified(path),
+                checked: Instant::now(),
+                frames: load_frames(renderer, path),
+                last_frame: None,
+            });
+        }
+
+        let cache = cache.as_mut()?;
+        if cache.frames.is_empty() {
+            return None;
+        }
+
+        let frame_duration = frame_duration.as_millis().max(1);
+        let frame = (elapsed.as_millis() / frame_duration) as usize % cache.frames.len();
+
+        let changed = cache.last_frame != Some(frame);
+        cache.last_frame = Some(frame);
+
+        Some((cache.frames[frame].clone(), changed))
+    }
+}
+
+/// Wallpaper covering a whole output
+#[derive(Debug, Clone)]
+pub struct WallpaperElement {
+    texture: Gles2Texture,
+    mode: WallpaperMode,
+    size: Size<i32, Logical>,
+    damaged: bool,
+}
+
+impl WallpaperElement {
+    pub fn new(
+        renderer: &mut Gles2Renderer,
+        output: &Output,
+        output_size: Size<i32, Logical>,
+        config: &WallpaperConfig,
+        elapsed: Duration,
+    ) -> Option<Self> {
+        let state = OutputState::for_output(output);
+        let (texture, changed) =
+            state.wallpaper(renderer, &config.path, config.frame_duration, elapsed)?;
+
+        Some(Self {
+            texture,
+            mode: config.mode,
+            size: output_size,
+            damaged: changed,
+        })
+    }
+
+    /// Destination rectangles of the image, relative to the output
+    fn quads(&self, scale: Scale<f64>) -> Vec<Rectangle<f64, Physical>> {
+        let output = self.size.to_f64().to_physical(scale);
+        let image = self.texture.size().to_f64();
+
+        let centered = |w: f64, h: f64| {
+            Rectangle::from_loc_and_size(((output.w - w) / 2.0, (output.h - h) / 2.0), (w, h))
+        };
+
+        match self.mode {
+            WallpaperMode::Stretch => vec![Rectangle::from_loc_and_size((0.0, 0.0), output)],
+            WallpaperMode::Center => vec![centered(image.w, image.h)],
+            WallpaperMode::Fit | WallpaperMode::Fill => {
+                let sx = output.w / image.w;
+                let sy = output.h / image.h;
+                let s = if self.mode == WallpaperMode::Fit {
+                    sx.min(sy)
+                } else {
+                    sx.max(sy)
+                };
+                vec![centered(image.w * s, image.h * s)]
+            }
+            WallpaperMode::Tile => {
+                let mut quads = Vec::new();
+                let mut y = 0.0;
+                while y < output.h {
+                    let mut x = 0.0;
+                    while x < output.w {
+                        quads.push(Rectangle::from_loc_and_size((x, y), (image.w, image.h)));
+                        x += image.w;
+                    }
+                    y += image.h;
+                }
+                quads
+            }
+        }
+    }
+}
+
+impl RenderElement<Gles2Renderer> for WallpaperElement {
+    fn id(&self) -> usize {
+        // Only one wallpaper per output
+        WALLPAPER_ELEMENT_ID
+    }
+
+    fn location(&self, _scale: impl Into<Scale<f64>>) -> Point<f64, Physical> {
+        (0.0, 0.0).into()
+    }
+
+    fn geometry(&self, scale: impl Into<Scale<f64>>) -> Rectangle<i32, Physical> {
+        Rectangle::from_loc_and_size((0, 0), self.size).to_physical_precise_round(scale)
+    }
+
+    fn accumulated_damage(
+        &self,
+        scale: impl Into<Scale<f64>>,
+        _: Option<SpaceOutputTuple<'_, '_>>,
+    ) -> Vec<Rectangle<i32, Physical>> {
+        if self.damaged {
+            vec![self.geometry(scale)]
+        } else {
+            vec![]
+        }
+    }
+
+    fn draw(
+        &self,
+        _renderer: &mut Gles2Renderer,
+        frame: &mut Gles2Frame,
+        scale: impl Into<Scale<f64>>,
+        location: Point<f64, Physical>,
+        damage: &[Rectangle<i32, Physical>],
+        _log: &slog::Logger,
+    ) -> Result<(), Gles2Error> {
+        let src =
+            Rectangle::<f64, Buffer>::from_loc_and_size((0.0, 0.0), self.texture.size().to_f64());
+
+        for quad in self.quads(scale.into()) {
+            let quad_i32 = quad.to_i32_round();
+
+            // Damage is relative to the element, the frame wants it relative to the quad
+            let quad_damage: Vec<_> = damage
+                .iter()
+                .filter_map(|rect| rect.intersection(quad_i32))
+                .map(|mut rect| {
+                    rect.loc -= quad_i32.loc;
+                    rect
+                })
+                .collect();
+
+            if quad_damage.is_empty() {
+                continue;
+            }
+
+            let mut dst = quad;
+            dst.loc += location;
+
+            frame.render_texture_from_to(
+                &self.texture,
+                src,
+                dst,
+                &quad_damage,
+                Transform::Normal,
+                1.0,
+            )?;
+        }
+
+        Ok(())
+    }
+
+    fn opaque_regions(
+        &self,
+        _scale: impl Into<Scale<f64>>,
+    ) -> Option<Vec<Rectangle<i32, Physical>>> {
+        None
+    }
+
+    fn z_index(&self) -> u8 {
+        RenderZindex::Background as u8 - 1
+    }
+}
@@ -0,0 +1,33 @@
+use std::collections::HashMap;
+
+use smithay::{
+    desktop::Window,
+    reexports::wayland_server::{backend::ObjectId, protocol::wl_surface::WlSurface, Resource},
+    utils::IsAlive,
+};
+
+/// Index of toplevel surfaces to their windows
+///
+/// Lets hot paths (like commit handling) find the owning window without scanning the whole space.
+#[derive(Debug, Default)]
+pub struct WindowIndex {
+    windows: HashMap<ObjectId, Window>,
+}
+
+impl WindowIndex {
+    pub fn insert(&mut self, window: &Window) {
+        self.windows
+            .insert(window.toplevel().wl_surface().id(), window.clone());
+    }
+
+    /// Get the window of a toplevel surface
+    pub fn get(&self, surface: &WlSurface) -> Option<&Window> {
+        self.windows.get(&surface.id())
+    }
+
+    /// Drop windows whose toplevel surface got destroyed
+    pub fn cleanup(&mut self) {
+        self.windows
+            .retain(|_, window| window.toplevel().wl_surface().alive());
+    }
+}
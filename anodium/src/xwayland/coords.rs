@@ -0,0 +1,44 @@
+//! Translation between the X11 root window space and the logical space
+//!
+//! X11 clients think in a single root window that starts at (0, 0), while outputs in the
+//! space may be placed anywhere, so X11 root covers the bounding box of all outputs.
+
+use smithay::{
+    desktop::Space,
+    utils::{Logical, Point, Rectangle, Size},
+};
+
+use crate::positioning;
+
+/// Area of the logical space covered by the X11 root window
+fn root_geometry(space: &Space) -> Rectangle<i32, Logical> {
+    positioning::outputs_geometry(space)
+}
+
+/// Convert a point in X11 root space to the logical space
+pub fn from_x11(space: &Space, point: impl Into<Point<i32, Logical>>) -> Point<i32, Logical> {
+    root_geometry(space).loc + point.into()
+}
+
+/// Convert a point in the logical space to X11 root space
+pub fn to_x11(space: &Space, point: Point<i32, Logical>) -> Point<i32, Logical> {
+    point - root_geometry(space).loc
+}
+
+/// Keep the window reachable, even if the X11 client requested a position outside of all outputs
+pub fn clamp_to_root(
+    space: &Space,
+    loc: Point<i32, Logical>,
+    size: Size<i32, Logical>,
+) -> Point<i32, Logical> {
+    let root = root_geometry(space);
+
+    let max_x = (root.loc.x + root.size.w - size.w).max(root.loc.x);
+    let max_y = (root.loc.y + root.size.h - size.h).max(root.loc.y);
+
+    (
+        loc.x.clamp(root.loc.x, max_x),
+        loc.y.clamp(root.loc.y, max_y),
+    )
+        .into()
+}
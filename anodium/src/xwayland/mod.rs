@@ -1,11 +1,12 @@
 use std::{collections::HashMap, convert::TryFrom, os::unix::net::UnixStream, time::Duration};
 
-use crate::{data::seat::SeatState, positioning, CalloopData, State};
+use crate::{data::window::WindowState, positioning, CalloopData, State};
 use calloop::{timer::Timer, LoopHandle};
 use slog_scope::{debug, error};
 use smithay::{
+    desktop::{space::RenderZindex, Space, Window, WindowSurfaceType},
     reexports::wayland_server::{protocol::wl_surface::WlSurface, Client, DisplayHandle, Resource},
-    utils::{Logical, Point},
+    utils::{Logical, Point, Size},
     xwayland::{XWayland, XWaylandEvent},
 };
 use x11rb::{
@@ -17,9 +18,16 @@ use x11rb::{
     },
 };
 
+/// Unmanaged windows are stacked above regular shell windows
+const OVERRIDE_REDIRECT_Z_INDEX: u8 = RenderZindex::Shell as u8 + 1;
+
+mod coords;
+
 mod pending_window;
 use pending_window::PendingWindow;
 
+pub mod selection;
+
 mod xwayland_client;
 use xwayland_client::XWaylandClient;
 
@@ -30,8 +38,13 @@ pub struct XWaylandState {
     client: Option<XWaylandClient>,
     client_token: Option<calloop::RegistrationToken>,
 
-    unpaired_surfaces: HashMap<u32, (X11Window, Point<i32, Logical>)>,
+    unpaired_surfaces: HashMap<u32, (X11Window, Point<i32, Logical>, bool)>,
     windows_awaiting_map: Vec<PendingWindow>,
+    override_redirect_windows: HashMap<X11Window, Window>,
+    /// Managed windows, with the last location that was sent to X11
+    managed_windows: HashMap<X11Window, (Window, Option<Point<i32, Logical>>)>,
+
+    selection: selection::SelectionState,
 }
 
 impl XWaylandState {
@@ -56,6 +69,9 @@ impl XWaylandState {
             client_token: None,
             unpaired_surfaces: Default::default(),
             windows_awaiting_map: Default::default(),
+            override_redirect_windows: Default::default(),
+            managed_windows: Default::default(),
+            selection: Default::default(),
         }
     }
 
@@ -68,6 +84,33 @@ impl XWaylandState {
         }
     }
 
+    /// Ask a managed X11 window to take the given size
+    ///
+    /// The X11 counterpart of configuring the size of an xdg toplevel.
+    pub fn configure_size(&self, window: &Window, size: Size<i32, Logical>) {
+        let client = match self.client.as_ref() {
+            Some(client) => client,
+            None => return,
+        };
+
+        let x11_window = self
+            .managed_windows
+            .iter()
+            .find(|(_, (w, _))| w == window)
+            .map(|(x11_window, _)| *x11_window);
+
+        if let Some(x11_window) = x11_window {
+            let aux = ConfigureWindowAux::default()
+                .width(size.w.max(1) as u32)
+                .height(size.h.max(1) as u32);
+
+            if let Err(err) = client.conn.configure_window(x11_window, &aux) {
+                error!("Failed to configure X11 window: {}", err);
+            }
+            client.conn.flush().ok();
+        }
+    }
+
     fn ready(
         &mut self,
         loop_handle: &LoopHandle<CalloopData>,
@@ -93,6 +136,9 @@ impl XWaylandState {
         self.client.take();
         self.unpaired_surfaces.clear();
         self.windows_awaiting_map.clear();
+        self.override_redirect_windows.clear();
+        self.managed_windows.clear();
+        self.selection = Default::default();
 
         if let Some(token) = self.client_token.take() {
             loop_handle.remove(token);
@@ -111,6 +157,27 @@ impl XWaylandState {
             .ok();
     }
 
+    /// Find the unmanaged window surface under the given point
+    ///
+    /// Overlapping unmanaged windows (like nested menus) are checked from the topmost down.
+    pub fn surface_under(
+        &self,
+        space: &Space,
+        position: Point<f64, Logical>,
+    ) -> Option<(Window, WlSurface, Point<i32, Logical>)> {
+        space
+            .windows()
+            .rev()
+            .filter(|window| WindowState::for_window(window).is_unmanaged())
+            .find_map(|window| {
+                let location = space.window_location(window)?;
+
+                window
+                    .surface_under(position - location.to_f64(), WindowSurfaceType::all())
+                    .map(|(surface, loc)| (window.clone(), surface, loc + location))
+            })
+    }
+
     fn handle_xwayland_event(state: &mut State, event: XWaylandEvent) {
         match event {
             XWaylandEvent::Ready {
@@ -156,10 +223,64 @@ fn handle_x11_event(state: &mut State, event: X11Event) -> Result<(), ReplyOrIdE
                 }
 
                 client.conn.configure_window(r.window, &aux)?;
+
+                let position_requested =
+                    r.value_mask & (u16::from(ConfigWindow::X) | u16::from(ConfigWindow::Y)) != 0;
+
+                if position_requested {
+                    if let Some((window, _)) = state.xwayland.managed_windows.get(&r.window) {
+                        let mut size = window.geometry().size;
+                        if r.value_mask & u16::from(ConfigWindow::WIDTH) != 0 {
+                            size.w = r.width as i32;
+                        }
+                        if r.value_mask & u16::from(ConfigWindow::HEIGHT) != 0 {
+                            size.h = r.height as i32;
+                        }
+
+                        let loc = coords::from_x11(&state.space, (r.x as i32, r.y as i32));
+                        let loc = coords::clamp_to_root(&state.space, loc, size);
+
+                        state.space.map_window(window, loc, None, false);
+                    }
+                }
             }
             X11Event::MapRequest(r) => {
                 client.conn.map_window(r.window)?;
             }
+            X11Event::ConfigureNotify(n) => {
+                // Unmanaged windows move themselves, so follow them around
+                if let Some(window) = state.xwayland.override_redirect_windows.get(&n.window) {
+                    let loc = coords::from_x11(&state.space, (n.x as i32, n.y as i32));
+                    state
+                        .space
+                        .map_window(window, loc, Some(OVERRIDE_REDIRECT_Z_INDEX), false);
+                }
+            }
+            X11Event::UnmapNotify(n) => {
+                if let Some(window) = state.xwayland.override_redirect_windows.remove(&n.window) {
+                    state.space.unmap_window(&window);
+                }
+                state.xwayland.managed_windows.remove(&n.window);
+            }
+            X11Event::DestroyNotify(n) => {
+                if let Some(window) = state.xwayland.override_redirect_windows.remove(&n.window) {
+                    state.space.unmap_window(&window);
+                }
+                state.xwayland.managed_windows.remove(&n.window);
+                selection::window_destroyed(state, n.window);
+            }
+            X11Event::SelectionRequest(req) => {
+                selection::handle_selection_request(state, &client, req)?;
+            }
+            X11Event::SelectionNotify(n) => {
+                selection::handle_selection_notify(state, &client, n)?;
+            }
+            X11Event::PropertyNotify(n) => {
+                selection::handle_property_notify(state, &client, n)?;
+            }
+            X11Event::XfixesSelectionNotify(n) => {
+                selection::handle_xfixes_selection_notify(state, &client, n)?;
+            }
             X11Event::ClientMessage(msg) => {
                 if msg.type_ == client.atoms.WL_SURFACE_ID {
                     let location = client
@@ -169,6 +290,13 @@ fn handle_x11_event(state: &mut State, event: X11Event) -> Result<(), ReplyOrIdE
                         .map(|geo| (geo.x as i32, geo.y as i32).into())
                         .unwrap_or_default();
 
+                    let override_redirect = client
+                        .conn
+                        .get_window_attributes(msg.window)?
+                        .reply()
+                        .map(|attrs| attrs.override_redirect)
+                        .unwrap_or(false);
+
                     let protocol_id = msg.data.as_data32()[0];
                     let surface = client
                         .wl_client
@@ -181,7 +309,8 @@ fn handle_x11_event(state: &mut State, event: X11Event) -> Result<(), ReplyOrIdE
                                 msg.window, protocol_id, surface,
                             );
 
-                            if let Some(window) = PendingWindow::new(msg.window, surface, location)
+                            if let Some(window) =
+                                PendingWindow::new(msg.window, surface, location, override_redirect)
                             {
                                 handle_new_window(state, window);
                             }
@@ -192,7 +321,7 @@ fn handle_x11_event(state: &mut State, event: X11Event) -> Result<(), ReplyOrIdE
                             state
                                 .xwayland
                                 .unpaired_surfaces
-                                .insert(protocol_id, (msg.window, location));
+                                .insert(protocol_id, (msg.window, location, override_redirect));
                         }
                     }
                 }
@@ -207,8 +336,66 @@ fn handle_x11_event(state: &mut State, event: X11Event) -> Result<(), ReplyOrIdE
 }
 
 fn on_window_map(state: &mut State, pending: PendingWindow) {
-    let pointer_pos = SeatState::for_seat(&state.seat).pointer_pos();
-    positioning::position_window_center(&mut state.space, pending.window, pointer_pos);
+    state.window_index.insert(&pending.window);
+
+    if pending.override_redirect {
+        WindowState::for_window(&pending.window).set_unmanaged(true);
+
+        // Tooltips, menus and dropdowns are placed at their requested position, above regular windows
+        let loc = coords::from_x11(&state.space, pending.location);
+        state
+            .space
+            .map_window(&pending.window, loc, Some(OVERRIDE_REDIRECT_Z_INDEX), false);
+
+        state
+            .xwayland
+            .override_redirect_windows
+            .insert(pending.x11_window, pending.window);
+    } else {
+        state
+            .xwayland
+            .managed_windows
+            .insert(pending.x11_window, (pending.window.clone(), None));
+
+        let output = state.new_window_output();
+        positioning::position_window_center(&mut state.space, pending.window, output.as_ref());
+    }
+}
+
+/// Let X11 know where its managed windows ended up in the space
+///
+/// Keeps X11 root relative coordinates (used eg. for menu placement) aligned with the Wayland side.
+pub fn sync_window_locations(state: &mut State) {
+    let client = match state.xwayland.client.clone() {
+        Some(client) => client,
+        None => return,
+    };
+
+    let mut changed = false;
+
+    for (x11_window, (window, last_location)) in state.xwayland.managed_windows.iter_mut() {
+        let location = match state.space.window_location(window) {
+            Some(location) => location,
+            None => continue,
+        };
+
+        if *last_location == Some(location) {
+            continue;
+        }
+        *last_location = Some(location);
+
+        let location = coords::to_x11(&state.space, location);
+        let aux = ConfigureWindowAux::default().x(location.x).y(location.y);
+
+        if let Err(err) = client.conn.configure_window(*x11_window, &aux) {
+            error!("Failed to configure X11 window: {}", err);
+        }
+        changed = true;
+    }
+
+    if changed {
+        client.conn.flush().ok();
+    }
 }
 
 fn handle_new_window(state: &mut State, pending: PendingWindow) {
@@ -242,12 +429,14 @@ pub fn handle_commit(state: &mut State, surface: &WlSurface) {
             if client == xwayland_client.wl_client {
                 // Is the surface among the unpaired surfaces (see comment next to WL_SURFACE_ID
                 // handling above)
-                if let Some((window, location)) = state
+                if let Some((window, location, override_redirect)) = state
                     .xwayland
                     .unpaired_surfaces
                     .remove(&surface.id().protocol_id())
                 {
-                    if let Some(window) = PendingWindow::new(window, surface.clone(), location) {
+                    if let Some(window) =
+                        PendingWindow::new(window, surface.clone(), location, override_redirect)
+                    {
                         handle_new_window(state, window);
                     }
                 }
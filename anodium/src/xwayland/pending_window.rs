@@ -1,6 +1,5 @@
 use slog_scope::{debug, error};
 use smithay::{
-    backend::renderer::utils::RendererSurfaceStateUserData,
     desktop::{Kind, Window, X11Surface},
     reexports::wayland_server::protocol::wl_surface::WlSurface,
     utils::{Logical, Point},
@@ -9,10 +8,15 @@ use smithay::{
 
 use x11rb::protocol::xproto::Window as X11Window;
 
+use crate::data::surface;
+
 #[derive(Debug)]
 pub struct PendingWindow {
     pub window: Window,
-    _location: Point<i32, Logical>,
+    pub x11_window: X11Window,
+    pub location: Point<i32, Logical>,
+    /// Unmanaged window (tooltip, menu, dropdown) that positions itself
+    pub override_redirect: bool,
 }
 
 impl PendingWindow {
@@ -20,6 +24,7 @@ impl PendingWindow {
         window: X11Window,
         surface: WlSurface,
         location: Point<i32, Logical>,
+        override_redirect: bool,
     ) -> Option<Self> {
         debug!("Matched X11 surface {:x?} to {:x?}", window, surface);
 
@@ -30,22 +35,16 @@ impl PendingWindow {
         }
 
         let x11surface = X11Surface { surface };
-        let window = Window::new(Kind::X11(x11surface));
 
         Some(Self {
-            window,
-            _location: location,
+            window: Window::new(Kind::X11(x11surface)),
+            x11_window: window,
+            location,
+            override_redirect,
         })
     }
 
     pub fn is_buffer_attached(&self) -> bool {
-        // Panic free version of `with_renderer_surface_state`
-        compositor::with_states(self.window.toplevel().wl_surface(), |states| {
-            if let Some(data) = states.data_map.get::<RendererSurfaceStateUserData>() {
-                data.borrow().wl_buffer().is_some()
-            } else {
-                false
-            }
-        })
+        surface::is_buffer_attached(self.window.toplevel().wl_surface())
     }
 }
@@ -0,0 +1,648 @@
+//! Clipboard bridge between X11 and Wayland
+//!
+//! When a Wayland client owns the selection, the WM window takes ownership of the X11 `CLIPBOARD`
+//! selection and proxies the transfers requested by X11 clients.
+//! When an X11 client owns it, its targets are advertised as the Wayland selection
+//! and converted on demand.
+//!
+//! Conversions of the X11 selection all go through the same property of the WM window,
+//! so they are queued and run one at a time.
+//! All transfers go through non-blocking pipes driven by the event loop.
+//! Data too big for a single X11 request goes through incremental (`INCR`) transfers,
+//! chunk by chunk, each one once the receiver deleted the previous one from the property.
+
+use std::{
+    collections::VecDeque,
+    fs::File,
+    io::{ErrorKind, Read, Write},
+    os::unix::io::{AsRawFd, FromRawFd, RawFd},
+};
+
+use calloop::{generic::Generic, Interest, LoopHandle, Mode, PostAction};
+use slog_scope::{error, warn};
+use smithay::{
+    reexports::{
+        nix::{
+            fcntl::{self, FcntlArg, OFlag},
+            unistd,
+        },
+        wayland_server::protocol::wl_data_source::WlDataSource,
+    },
+    wayland::data_device,
+};
+use x11rb::{
+    connection::Connection,
+    errors::ReplyOrIdError,
+    protocol::{
+        xfixes::SelectionNotifyEvent as XfixesSelectionNotifyEvent,
+        xproto::{
+            Atom, AtomEnum, ChangeWindowAttributesAux, ConnectionExt as _, EventMask, PropMode,
+            Property, PropertyNotifyEvent, SelectionNotifyEvent, SelectionRequestEvent,
+            Window as X11Window, SELECTION_NOTIFY_EVENT,
+        },
+    },
+    wrapper::ConnectionExt as _,
+};
+
+use super::xwayland_client::XWaylandClient;
+use crate::{CalloopData, State};
+
+const TEXT_MIME: &str = "text/plain;charset=utf-8";
+
+/// Size of the chunks of incremental transfers, well below the smallest maximum request length
+const INCR_CHUNK_SIZE: usize = 64 * 1024;
+
+#[derive(Debug, Default)]
+pub struct SelectionState {
+    /// Mime types of the Wayland selection, while the WM owns X11 `CLIPBOARD` on its behalf
+    wayland_mime_types: Option<Vec<String>>,
+    /// Conversions of the X11 selection, the first one is in progress
+    conversions: VecDeque<Conversion>,
+    /// Data received so far, while the current conversion is an incremental transfer
+    incoming: Option<Vec<u8>>,
+    /// Incremental transfers of the Wayland selection to X11 clients
+    outgoing: Vec<OutgoingTransfer>,
+}
+
+/// Conversion of the X11 selection to a target
+#[derive(Debug)]
+struct Conversion {
+    target: Atom,
+    /// Pipe of the Wayland client waiting for the data, `None` for the `TARGETS` list
+    file: Option<File>,
+}
+
+/// Wayland selection sent in chunks to a property of the requestor
+#[derive(Debug)]
+struct OutgoingTransfer {
+    requestor: X11Window,
+    property: Atom,
+    target: Atom,
+    data: Vec<u8>,
+    offset: usize,
+    /// The final empty chunk was written, the transfer ends once it is deleted
+    done: bool,
+}
+
+fn mime_to_atom(client: &XWaylandClient, mime: &str) -> Result<Atom, ReplyOrIdError> {
+    if mime == TEXT_MIME {
+        return Ok(client.atoms.UTF8_STRING);
+    }
+
+    Ok(client
+        .conn
+        .intern_atom(false, mime.as_bytes())?
+        .reply()?
+        .atom)
+}
+
+fn atom_to_mime(client: &XWaylandClient, atom: Atom) -> Result<Option<String>, ReplyOrIdError> {
+    if atom == client.atoms.UTF8_STRING {
+        return Ok(Some(TEXT_MIME.into()));
+    }
+
+    let name = client.conn.get_atom_name(atom)?.reply()?.name;
+
+    // Only forward targets that look like mime types, X11 specific ones (TARGETS, TIMESTAMP, etc.)
+    // mean nothing to Wayland clients
+    Ok(String::from_utf8(name)
+        .ok()
+        .filter(|name| name.contains('/')))
+}
+
+/// Property the requested selection is stored in
+fn reply_property(req: &SelectionRequestEvent) -> Atom {
+    if req.property == x11rb::NONE {
+        // Obsolete clients expect the target to be used as the property
+        req.target
+    } else {
+        req.property
+    }
+}
+
+fn send_notify(
+    client: &XWaylandClient,
+    req: &SelectionRequestEvent,
+    success: bool,
+) -> Result<(), ReplyOrIdError> {
+    let property = if success {
+        reply_property(req)
+    } else {
+        x11rb::NONE
+    };
+
+    let event = SelectionNotifyEvent {
+        response_type: SELECTION_NOTIFY_EVENT,
+        sequence: 0,
+        time: req.time,
+        requestor: req.requestor,
+        selection: req.selection,
+        target: req.target,
+        property,
+    };
+
+    client
+        .conn
+        .send_event(false, req.requestor, EventMask::NO_EVENT, event)?;
+    client.conn.flush()?;
+
+    Ok(())
+}
+
+/// A Wayland client set a new selection, take over X11 `CLIPBOARD` on its behalf
+pub fn wayland_selection_changed(state: &mut State, source: Option<&WlDataSource>) {
+    let client = match state.xwayland.client.clone() {
+        Some(client) => client,
+        None => return,
+    };
+
+    let mime_types = source.and_then(|source| {
+        data_device::with_source_metadata(source, |meta| meta.mime_types.clone()).ok()
+    });
+
+    // Don't clear the clipboard of X11 clients if we were not the owner
+    if mime_types.is_none() && state.xwayland.selection.wayland_mime_types.is_none() {
+        return;
+    }
+
+    let owner = if mime_types.is_some() {
+        client.wm_window
+    } else {
+        x11rb::NONE
+    };
+
+    state.xwayland.selection.wayland_mime_types = mime_types;
+
+    let res = client
+        .conn
+        .set_selection_owner(owner, client.atoms.CLIPBOARD, x11rb::CURRENT_TIME)
+        .and_then(|_| client.conn.flush());
+
+    if let Err(err) = res {
+        error!("Failed to set X11 selection owner: {}", err);
+    }
+}
+
+/// A Wayland client wants to paste the selection owned by an X11 client
+pub fn send_selection(state: &mut State, mime_type: String, fd: RawFd) {
+    let file = unsafe { File::from_raw_fd(fd) };
+
+    let client = match state.xwayland.client.clone() {
+        Some(client) => client,
+        None => return,
+    };
+
+    let res = mime_to_atom(&client, &mime_type).and_then(|target| {
+        let conversion = Conversion {
+            target,
+            file: Some(file),
+        };
+        queue_conversion(state, &client, conversion)
+    });
+
+    if let Err(err) = res {
+        error!("Failed to convert X11 selection: {}", err);
+    }
+}
+
+/// Queue a conversion of the X11 selection, it starts once the previous ones are done
+fn queue_conversion(
+    state: &mut State,
+    client: &XWaylandClient,
+    conversion: Conversion,
+) -> Result<(), ReplyOrIdError> {
+    let conversions = &mut state.xwayland.selection.conversions;
+
+    let idle = conversions.is_empty();
+    conversions.push_back(conversion);
+
+    if idle {
+        start_conversion(state, client)?;
+    }
+
+    Ok(())
+}
+
+/// Ask the selection owner to convert the selection for the first queued conversion
+fn start_conversion(state: &mut State, client: &XWaylandClient) -> Result<(), ReplyOrIdError> {
+    let target = match state.xwayland.selection.conversions.front() {
+        Some(conversion) => conversion.target,
+        None => return Ok(()),
+    };
+
+    let res = client
+        .conn
+        .convert_selection(
+            client.wm_window,
+            client.atoms.CLIPBOARD,
+            target,
+            client.atoms._ANODIUM_SELECTION,
+            x11rb::CURRENT_TIME,
+        )
+        .and_then(|_| client.conn.flush());
+
+    if let Err(err) = res {
+        // Nothing would ever finish the queue, dropping the files closes the pipes
+        state.xwayland.selection.conversions.clear();
+        return Err(err.into());
+    }
+
+    Ok(())
+}
+
+/// The first queued conversion is done, hand its data over and start the next one
+///
+/// Without data, like when the conversion failed, the waiting client only gets its pipe closed.
+fn finish_conversion(
+    state: &mut State,
+    client: &XWaylandClient,
+    data: Option<Vec<u8>>,
+) -> Result<(), ReplyOrIdError> {
+    let selection = &mut state.xwayland.selection;
+    selection.incoming = None;
+
+    if let Some(Conversion {
+        file: Some(file), ..
+    }) = selection.conversions.pop_front()
+    {
+        if let Some(data) = data {
+            write_async(&state._loop_handle, file, data);
+        }
+    }
+
+    start_conversion(state, client)
+}
+
+/// An X11 client asks for the selection owned by the WM
+pub fn handle_selection_request(
+    state: &mut State,
+    client: &XWaylandClient,
+    req: SelectionRequestEvent,
+) -> Result<(), ReplyOrIdError> {
+    let mime_types = match &state.xwayland.selection.wayland_mime_types {
+        Some(mime_types) if req.selection == client.atoms.CLIPBOARD => mime_types.clone(),
+        _ => return send_notify(client, &req, false),
+    };
+
+    if req.target == client.atoms.TARGETS {
+        let mut targets = vec![client.atoms.TARGETS];
+        for mime in mime_types.iter() {
+            targets.push(mime_to_atom(client, mime)?);
+        }
+
+        client.conn.change_property32(
+            PropMode::REPLACE,
+            req.requestor,
+            reply_property(&req),
+            AtomEnum::ATOM,
+            &targets,
+        )?;
+
+        return send_notify(client, &req, true);
+    }
+
+    let mime_type = match atom_to_mime(client, req.target)? {
+        Some(mime_type) if mime_types.contains(&mime_type) => mime_type,
+        _ => return send_notify(client, &req, false),
+    };
+
+    let (read_fd, write_fd) = match unistd::pipe2(OFlag::O_CLOEXEC) {
+        Ok(pipe) => pipe,
+        Err(err) => {
+            error!("Failed to create selection pipe: {}", err);
+            return send_notify(client, &req, false);
+        }
+    };
+
+    let res = data_device::request_data_device_client_selection(&state.seat, mime_type, write_fd);
+    unistd::close(write_fd).ok();
+
+    if let Err(err) = res {
+        error!("Failed to request Wayland selection: {:?}", err);
+        unistd::close(read_fd).ok();
+        return send_notify(client, &req, false);
+    }
+
+    // Only our end of the pipe is made non-blocking, the Wayland client gets a regular one
+    fcntl::fcntl(read_fd, FcntlArg::F_SETFL(OFlag::O_NONBLOCK)).ok();
+    let reader = unsafe { File::from_raw_fd(read_fd) };
+
+    let client = client.clone();
+    let mut buffer = Vec::new();
+
+    let res = state._loop_handle.insert_source(
+        Generic::new(reader, Interest::READ, Mode::Level),
+        move |_, reader, data| {
+            let mut chunk = [0u8; 4096];
+
+            loop {
+                match reader.read(&mut chunk) {
+                    Ok(0) => {
+                        let buffer = std::mem::take(&mut buffer);
+
+                        let res = if buffer.len() > INCR_CHUNK_SIZE {
+                            start_outgoing_transfer(&mut data.state, &client, &req, buffer)
+                        } else {
+                            client
+                                .conn
+                                .change_property8(
+                                    PropMode::REPLACE,
+                                    req.requestor,
+                                    reply_property(&req),
+                                    req.target,
+                                    &buffer,
+                                )
+                                .map_err(ReplyOrIdError::from)
+                                .and_then(|_| send_notify(&client, &req, true))
+                        };
+
+                        if let Err(err) = res {
+                            error!("Failed to send selection to X11 client: {}", err);
+                        }
+
+                        return Ok(PostAction::Remove);
+                    }
+                    Ok(n) => buffer.extend_from_slice(&chunk[..n]),
+                    Err(err) if err.kind() == ErrorKind::WouldBlock => {
+                        return Ok(PostAction::Continue)
+                    }
+                    Err(err) if err.kind() == ErrorKind::Interrupted => {}
+                    Err(err) => {
+                        warn!("Failed to read Wayland selection: {}", err);
+                        send_notify(&client, &req, false).ok();
+                        return Ok(PostAction::Remove);
+                    }
+                }
+            }
+        },
+    );
+
+    if let Err(err) = res {
+        error!("Failed to insert selection source: {}", err);
+    }
+
+    Ok(())
+}
+
+/// Announce an incremental transfer to the requestor, the chunks follow as it deletes them
+fn start_outgoing_transfer(
+    state: &mut State,
+    client: &XWaylandClient,
+    req: &SelectionRequestEvent,
+    data: Vec<u8>,
+) -> Result<(), ReplyOrIdError> {
+    let property = reply_property(req);
+
+    client.conn.change_window_attributes(
+        req.requestor,
+        &ChangeWindowAttributesAux::new().event_mask(EventMask::PROPERTY_CHANGE),
+    )?;
+    // The INCR property holds a lower bound of the size
+    client.conn.change_property32(
+        PropMode::REPLACE,
+        req.requestor,
+        property,
+        client.atoms.INCR,
+        &[data.len().min(u32::MAX as usize) as u32],
+    )?;
+
+    state.xwayland.selection.outgoing.push(OutgoingTransfer {
+        requestor: req.requestor,
+        property,
+        target: req.target,
+        data,
+        offset: 0,
+        done: false,
+    });
+
+    send_notify(client, req, true)
+}
+
+/// The requestor of an incremental transfer deleted the last chunk, send the next one
+fn send_next_chunk(
+    state: &mut State,
+    client: &XWaylandClient,
+    requestor: X11Window,
+    property: Atom,
+) -> Result<(), ReplyOrIdError> {
+    let outgoing = &mut state.xwayland.selection.outgoing;
+
+    let index = outgoing
+        .iter()
+        .position(|transfer| transfer.requestor == requestor && transfer.property == property);
+    let index = match index {
+        Some(index) => index,
+        None => return Ok(()),
+    };
+
+    let transfer = &mut outgoing[index];
+
+    if !transfer.done {
+        let end = (transfer.offset + INCR_CHUNK_SIZE).min(transfer.data.len());
+        let chunk = &transfer.data[transfer.offset..end];
+
+        // An empty chunk ends the transfer
+        transfer.done = chunk.is_empty();
+        client.conn.change_property8(
+            PropMode::REPLACE,
+            requestor,
+            property,
+            transfer.target,
+            chunk,
+        )?;
+        transfer.offset = end;
+
+        client.conn.flush()?;
+        return Ok(());
+    }
+
+    outgoing.remove(index);
+
+    // Other transfers to the same window still need its property changes
+    if !outgoing
+        .iter()
+        .any(|transfer| transfer.requestor == requestor)
+    {
+        client.conn.change_window_attributes(
+            requestor,
+            &ChangeWindowAttributesAux::new().event_mask(EventMask::NO_EVENT),
+        )?;
+        client.conn.flush()?;
+    }
+
+    Ok(())
+}
+
+/// The X11 selection owner wrote the next chunk of an incremental transfer
+fn receive_next_chunk(state: &mut State, client: &XWaylandClient) -> Result<(), ReplyOrIdError> {
+    if state.xwayland.selection.incoming.is_none() {
+        return Ok(());
+    }
+
+    // Deleting the chunk asks the owner for the next one
+    let reply = client
+        .conn
+        .get_property(
+            true,
+            client.wm_window,
+            client.atoms._ANODIUM_SELECTION,
+            AtomEnum::ANY,
+            0,
+            u32::MAX / 4,
+        )?
+        .reply()?;
+
+    if reply.value.is_empty() {
+        // An empty chunk ends the transfer
+        let data = state.xwayland.selection.incoming.take();
+        return finish_conversion(state, client, data);
+    }
+
+    if let Some(data) = state.xwayland.selection.incoming.as_mut() {
+        data.extend_from_slice(&reply.value);
+    }
+
+    Ok(())
+}
+
+/// A property involved in an incremental transfer changed
+pub fn handle_property_notify(
+    state: &mut State,
+    client: &XWaylandClient,
+    event: PropertyNotifyEvent,
+) -> Result<(), ReplyOrIdError> {
+    if event.window == client.wm_window {
+        if event.atom == client.atoms._ANODIUM_SELECTION && event.state == Property::NEW_VALUE {
+            receive_next_chunk(state, client)?;
+        }
+    } else if event.state == Property::DELETE {
+        send_next_chunk(state, client, event.window, event.atom)?;
+    }
+
+    Ok(())
+}
+
+/// Drop the incremental transfers to a window that went away
+pub fn window_destroyed(state: &mut State, window: X11Window) {
+    state
+        .xwayland
+        .selection
+        .outgoing
+        .retain(|transfer| transfer.requestor != window);
+}
+
+/// Ownership of an X11 selection changed
+pub fn handle_xfixes_selection_notify(
+    state: &mut State,
+    client: &XWaylandClient,
+    event: XfixesSelectionNotifyEvent,
+) -> Result<(), ReplyOrIdError> {
+    // Ignore our own ownership changes, and keep the Wayland selection when the X11 owner goes away
+    if event.selection != client.atoms.CLIPBOARD
+        || event.owner == client.wm_window
+        || event.owner == x11rb::NONE
+    {
+        return Ok(());
+    }
+
+    // An X11 client took over the clipboard, ask it what it has to offer
+    state.xwayland.selection.wayland_mime_types = None;
+
+    let conversion = Conversion {
+        target: client.atoms.TARGETS,
+        file: None,
+    };
+    queue_conversion(state, client, conversion)
+}
+
+/// Result of a selection conversion requested by the WM
+pub fn handle_selection_notify(
+    state: &mut State,
+    client: &XWaylandClient,
+    event: SelectionNotifyEvent,
+) -> Result<(), ReplyOrIdError> {
+    if event.requestor != client.wm_window || event.selection != client.atoms.CLIPBOARD {
+        return Ok(());
+    }
+
+    // Only the first queued conversion is in progress, and not while its chunks arrive
+    let selection = &state.xwayland.selection;
+    let current = selection
+        .conversions
+        .front()
+        .map(|conversion| conversion.target);
+    if selection.incoming.is_some() || current != Some(event.target) {
+        return Ok(());
+    }
+
+    if event.property == x11rb::NONE {
+        return finish_conversion(state, client, None);
+    }
+
+    let reply = client
+        .conn
+        .get_property(
+            true,
+            client.wm_window,
+            event.property,
+            AtomEnum::ANY,
+            0,
+            u32::MAX / 4,
+        )?
+        .reply()?;
+
+    if event.target == client.atoms.TARGETS {
+        let mut mime_types = Vec::new();
+        if let Some(atoms) = reply.value32() {
+            for atom in atoms {
+                if let Some(mime_type) = atom_to_mime(client, atom)? {
+                    mime_types.push(mime_type);
+                }
+            }
+        }
+
+        data_device::set_data_device_selection(&state.display, &state.seat, mime_types);
+        return finish_conversion(state, client, None);
+    }
+
+    if reply.type_ == client.atoms.INCR {
+        // Deleting the INCR property above started the transfer, the chunks follow
+        // as property changes of the WM window
+        state.xwayland.selection.incoming = Some(Vec::new());
+        return Ok(());
+    }
+
+    finish_conversion(state, client, Some(reply.value))
+}
+
+/// Write the data to the Wayland client pipe, without blocking the event loop
+fn write_async(loop_handle: &LoopHandle<'static, CalloopData>, file: File, data: Vec<u8>) {
+    fcntl::fcntl(file.as_raw_fd(), FcntlArg::F_SETFL(OFlag::O_NONBLOCK)).ok();
+
+    let mut offset = 0;
+
+    let res = loop_handle.insert_source(
+        Generic::new(file, Interest::WRITE, Mode::Level),
+        move |_, file, _| loop {
+            match file.write(&data[offset..]) {
+                Ok(n) => {
+                    offset += n;
+
+                    if offset >= data.len() || n == 0 {
+                        return Ok(PostAction::Remove);
+                    }
+                }
+                Err(err) if err.kind() == ErrorKind::WouldBlock => return Ok(PostAction::Continue),
+                Err(err) if err.kind() == ErrorKind::Interrupted => {}
+                Err(err) => {
+                    warn!("Failed to write X11 selection: {}", err);
+                    return Ok(PostAction::Remove);
+                }
+            }
+        },
+    );
+
+    if let Err(err) = res {
+        error!("Failed to insert selection source: {}", err);
+    }
+}
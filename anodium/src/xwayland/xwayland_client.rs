@@ -5,7 +5,11 @@ use x11rb::{
     connection::Connection,
     protocol::{
         composite::{ConnectionExt as _, Redirect},
-        xproto::{ChangeWindowAttributesAux, ConnectionExt as _, EventMask, WindowClass},
+        xfixes::{ConnectionExt as _, SelectionEventMask},
+        xproto::{
+            ChangeWindowAttributesAux, ConnectionExt as _, CreateWindowAux, EventMask,
+            Window as X11Window, WindowClass,
+        },
     },
     rust_connection::{DefaultStream, RustConnection},
 };
@@ -14,6 +18,11 @@ x11rb::atom_manager! {
     pub Atoms: AtomsCookie {
         WM_S0,
         WL_SURFACE_ID,
+        CLIPBOARD,
+        TARGETS,
+        UTF8_STRING,
+        INCR,
+        _ANODIUM_SELECTION,
         _ANODIUM_CLOSE_CONNECTION,
     }
 }
@@ -23,6 +32,8 @@ pub struct XWaylandClient {
     pub conn: Arc<RustConnection>,
     pub atoms: Atoms,
     pub wl_client: Client,
+    /// Window owned by the WM, used for selections
+    pub wm_window: X11Window,
 }
 
 impl XWaylandClient {
@@ -41,7 +52,8 @@ impl XWaylandClient {
         // Actually become the WM by redirecting some operations
         conn.change_window_attributes(
             screen.root,
-            &ChangeWindowAttributesAux::default().event_mask(EventMask::SUBSTRUCTURE_REDIRECT),
+            &ChangeWindowAttributesAux::default()
+                .event_mask(EventMask::SUBSTRUCTURE_REDIRECT | EventMask::SUBSTRUCTURE_NOTIFY),
         )?;
 
         // Tell XWayland that we are the WM by acquiring the WM_S0 selection. No X11 clients are accepted before this.
@@ -58,10 +70,21 @@ impl XWaylandClient {
             0,
             WindowClass::INPUT_OUTPUT,
             x11rb::COPY_FROM_PARENT,
-            &Default::default(),
+            // Chunks of incremental selection transfers arrive as property changes
+            &CreateWindowAux::new().event_mask(EventMask::PROPERTY_CHANGE),
         )?;
         conn.set_selection_owner(win, atoms.WM_S0, x11rb::CURRENT_TIME)?;
 
+        // Get notified about clipboard owner changes, so that it can be bridged to Wayland
+        conn.xfixes_query_version(5, 0)?.reply()?;
+        conn.xfixes_select_selection_input(
+            win,
+            atoms.CLIPBOARD,
+            SelectionEventMask::SET_SELECTION_OWNER
+                | SelectionEventMask::SELECTION_WINDOW_DESTROY
+                | SelectionEventMask::SELECTION_CLIENT_CLOSE,
+        )?;
+
         // XWayland wants us to do this to function properly...?
         conn.composite_redirect_subwindows(screen.root, Redirect::MANUAL)?;
 
@@ -73,6 +96,7 @@ impl XWaylandClient {
             conn: Arc::clone(&conn),
             atoms,
             wl_client: client,
+            wm_window: win,
         };
 
         let source = X11Source::new(